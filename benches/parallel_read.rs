@@ -0,0 +1,90 @@
+//! Measure how read throughput scales with the number of threads sharing
+//! one filesystem.
+//!
+//! The figures are informational only: nothing is compared against a
+//! baseline.  `parallel_reads_match_a_serial_read` in
+//! tests/test_concurrent.rs checks that the same workload reads correctly.
+//!
+//! Usage: cargo bench --bench parallel_read
+
+extern crate ext2;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use ext2::{Ext2, Mkfs, ReadAtDisk, ReadDisk};
+
+const FILES: usize = 256;
+const FILE_SIZE: usize = 128 << 10;
+const ROUNDS: usize = 4;
+
+/// Format an image holding `FILES` files, and write it to a temporary file.
+fn build_image() -> std::path::PathBuf {
+    let size = 64 << 20;
+    let fs = Mkfs::new()
+        .format(Cursor::new(vec![0; size]), size as u64)
+        .unwrap();
+    let data: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    for i in 0..FILES {
+        fs.create(format!("/{}", i))
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+    }
+    let path = env::temp_dir().join(format!("ext2-bench-{}.img", std::process::id()));
+    fs::write(&path, fs.into_inner().into_inner()).unwrap();
+    path
+}
+
+/// Read every file `ROUNDS` times, split between `threads` threads, and
+/// return the throughput in MiB/s.
+fn run<T: ReadDisk + Send + Sync + 'static>(fs: Arc<Ext2<T>>, threads: usize) -> f64 {
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let fs = fs.clone();
+            thread::spawn(move || {
+                let mut buf = Vec::with_capacity(FILE_SIZE);
+                for _ in 0..ROUNDS {
+                    for i in (t..FILES).step_by(threads) {
+                        buf.clear();
+                        let mut file = fs.open(format!("/{}", i)).unwrap();
+                        file.read_to_end(&mut buf).unwrap();
+                        assert_eq!(buf.len(), FILE_SIZE);
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let bytes = (FILES * FILE_SIZE * ROUNDS) as f64;
+    bytes / (1 << 20) as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let path = build_image();
+    let locked = Arc::new(Ext2::new(File::open(&path).unwrap()).unwrap());
+    let positional = Arc::new(Ext2::new(ReadAtDisk::new(File::open(&path).unwrap())).unwrap());
+    // Warm the page cache, so that the first run is not penalised.
+    run(positional.clone(), 1);
+
+    println!(
+        "{:>8} {:>14} {:>14}",
+        "threads", "locked MiB/s", "pread MiB/s"
+    );
+    for &threads in &[1, 2, 4, 8] {
+        println!(
+            "{:>8} {:>14.0} {:>14.0}",
+            threads,
+            run(locked.clone(), threads),
+            run(positional.clone(), threads)
+        );
+    }
+    fs::remove_file(&path).unwrap();
+}
@@ -28,14 +28,12 @@ fn load_superblock<P: AsRef<Path>>(path: P) -> Result<[u8; 1024], anyhow::Error>
 #[cfg(target_endian = "little")]
 fn new_superblock_copy(raw_block: &[u8]) -> Superblock {
     let length = mem::size_of::<Superblock>();
-    let mut superblock;
-    let superblock_buf;
+    let mut superblock = mem::MaybeUninit::<Superblock>::uninit();
     unsafe {
-        superblock = mem::uninitialized();
-        superblock_buf = slice::from_raw_parts_mut(&mut superblock as *mut _ as *mut u8, length);
+        let superblock_buf = slice::from_raw_parts_mut(superblock.as_mut_ptr() as *mut u8, length);
+        superblock_buf.copy_from_slice(&raw_block[..length]);
+        superblock.assume_init()
     }
-    superblock_buf.copy_from_slice(&raw_block[..length]);
-    superblock
 }
 
 fn main() -> anyhow::Result<()> {
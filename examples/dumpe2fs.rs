@@ -0,0 +1,68 @@
+//! Print a summary of an image's superblock, in the style of `dumpe2fs -h`.
+//!
+//! Usage: cargo run --example dumpe2fs [IMAGE]
+
+extern crate ext2;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use ext2::Superblock;
+use uuid::Uuid;
+
+fn main() -> anyhow::Result<()> {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "basic.ext2".to_owned());
+    let mut raw = [0; 1024];
+    let mut file = File::open(&path)?;
+    file.seek(SeekFrom::Start(1024))?;
+    file.read_exact(&mut raw)?;
+    let sb = Superblock::new(&raw)?;
+    sb.check()?;
+
+    let name = String::from_utf8_lossy(&sb.s_volume_name);
+    let name = name.trim_end_matches('\0');
+    row(
+        "Filesystem volume name",
+        if name.is_empty() { "<none>" } else { name },
+    );
+    row("Filesystem magic number", format!("0x{:04X}", sb.s_magic));
+    row("Filesystem revision #", sb.revision());
+    row("Filesystem UUID", Uuid::from_bytes(sb.s_uuid));
+    row(
+        "Filesystem features",
+        format!(
+            "{} {} {}",
+            sb.compat_features(),
+            sb.incompat_features(),
+            sb.ro_compat_features()
+        )
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" "),
+    );
+    row("Filesystem state", sb.state());
+    row("Errors behavior", sb.errors());
+    row("Filesystem OS type", sb.creator_os());
+    row("Inode count", sb.s_inodes_count);
+    row("Block count", sb.s_blocks_count);
+    row("Reserved block count", sb.s_r_blocks_count);
+    row("Free blocks", sb.s_free_blocks_count);
+    row("Free inodes", sb.s_free_inodes_count);
+    row("First block", sb.s_first_data_block);
+    row("Block size", sb.block_size());
+    row("Blocks per group", sb.s_blocks_per_group);
+    row("Inodes per group", sb.s_inodes_per_group);
+    row("Mount count", sb.s_mnt_count);
+    row("Maximum mount count", sb.s_max_mnt_count as i16);
+    row("First inode", sb.first_ino());
+    row("Inode size", sb.inode_size());
+    Ok(())
+}
+
+fn row<T: std::fmt::Display>(label: &str, value: T) {
+    println!("{:<26}{}", format!("{}:", label), value);
+}
@@ -7,7 +7,7 @@ use std::io::{self, Read};
 use image::{DecodingResult, ImageDecoder};
 use image::jpeg::{JPEGDecoder, JPEGEncoder};
 
-use ext2::{Disk, Ext2};
+use ext2::Ext2;
 
 fn main() {
     let ext2 = Ext2::new(File::open("basic.ext2").unwrap()).unwrap();
@@ -0,0 +1,500 @@
+//! alloc.rs: Block and inode allocation from the per-group bitmaps.
+//!
+//! Every change made through an `Allocator` is written straight back to
+//! disk: the bitmap itself, the free counts in the block group descriptor,
+//! and the free counts in the superblock.
+
+use std::convert::TryFrom;
+use std::io;
+
+use super::{disk, BlockGroupDescriptor, Ext2, Ext2Error, Superblock};
+
+/// A block or inode bitmap for a single block group.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Bitmap {
+    data: Vec<u8>,
+    len: u32,
+}
+
+impl Bitmap {
+    /// Wrap the raw bitmap block `data`, of which only the first `len` bits
+    /// are meaningful.
+    pub fn new(data: Vec<u8>, len: u32) -> Bitmap {
+        assert!(
+            len as usize <= data.len() * 8,
+            "Bitmap of {} bytes cannot hold {} bits",
+            data.len(),
+            len
+        );
+        Bitmap { data, len }
+    }
+
+    /// The number of meaningful bits.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_set(&self, bit: u32) -> bool {
+        self.data[bit as usize / 8] & (1 << (bit % 8)) != 0
+    }
+
+    pub fn set(&mut self, bit: u32) {
+        self.data[bit as usize / 8] |= 1 << (bit % 8);
+    }
+
+    pub fn clear(&mut self, bit: u32) {
+        self.data[bit as usize / 8] &= !(1 << (bit % 8));
+    }
+
+    /// The number of clear bits.
+    pub fn count_clear(&self) -> u32 {
+        (0..self.len).filter(|&bit| !self.is_set(bit)).count() as u32
+    }
+
+    /// The first clear bit at or after `start`.
+    pub fn find_clear(&self, start: u32) -> Option<u32> {
+        (start..self.len).find(|&bit| !self.is_set(bit))
+    }
+
+    /// The first run of clear bits at or after `start`, at most `max` bits
+    /// long.  Returns the first bit of the run and its length.
+    pub fn find_clear_run(&self, start: u32, max: u32) -> Option<(u32, u32)> {
+        let first = self.find_clear(start)?;
+        let len = (first..self.len)
+            .take(max as usize)
+            .take_while(|&bit| !self.is_set(bit))
+            .count() as u32;
+        Some((first, len))
+    }
+
+    /// The raw bitmap block.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Allocates and frees blocks and inodes on a filesystem.
+pub struct Allocator<'fs, T: disk::ReadDisk + 'fs> {
+    fs: &'fs Ext2<T>,
+    sb: Superblock,
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> Allocator<'fs, T> {
+    pub(crate) fn new(fs: &'fs Ext2<T>, sb: &Superblock) -> Allocator<'fs, T> {
+        Allocator { fs, sb: sb.clone() }
+    }
+
+    /// The block group containing `block`, and the block's index within it.
+    /// Blocks outside the filesystem are an error.
+    pub fn locate_block(&self, block: u32) -> io::Result<(u32, u32)> {
+        if block < self.sb.s_first_data_block || block >= self.sb.s_blocks_count {
+            return Err(Ext2Error::corrupt_block("block number out of range", block).into());
+        }
+        let relative = block - self.sb.s_first_data_block;
+        Ok((
+            relative / self.sb.s_blocks_per_group,
+            relative % self.sb.s_blocks_per_group,
+        ))
+    }
+
+    /// The number of blocks in block group `group`.  Only the last group may
+    /// be short.
+    fn blocks_in_group(&self, group: u32) -> u32 {
+        self.sb
+            .s_blocks_per_group
+            .min(self.sb.s_blocks_count - self.sb.group_first_block(group))
+    }
+
+    fn descriptor(&self, group: u32) -> io::Result<BlockGroupDescriptor> {
+        self.fs.get_block_group_descriptor(group, &self.sb)
+    }
+
+    fn read_bitmap(&self, block: u32, len: u32) -> io::Result<Bitmap> {
+        let mut data = vec![0; self.sb.block_size() as usize];
+        if len as usize > data.len() * 8 {
+            return Err(Ext2Error::corrupt(format!(
+                "{} bits per group do not fit in a bitmap block",
+                len
+            ))
+            .into());
+        }
+        self.fs.read_block(block, &mut data, &self.sb)?;
+        Ok(Bitmap::new(data, len))
+    }
+
+    pub fn block_bitmap(&self, group: u32) -> io::Result<Bitmap> {
+        let descriptor = self.descriptor(group)?;
+        self.read_bitmap(descriptor.bg_block_bitmap, self.blocks_in_group(group))
+    }
+
+    pub fn inode_bitmap(&self, group: u32) -> io::Result<Bitmap> {
+        let descriptor = self.descriptor(group)?;
+        self.read_bitmap(descriptor.bg_inode_bitmap, self.sb.s_inodes_per_group)
+    }
+
+    /// Choose the block group for a new inode whose parent directory is
+    /// `parent`.
+    ///
+    /// Files go in their parent's group, so that a directory's contents are
+    /// stored together.  Directories are spread out: they go in the group
+    /// with the most free blocks among those with an above-average number of
+    /// free inodes.
+    fn inode_group(&self, parent: u32, directory: bool) -> io::Result<u32> {
+        let (parent_group, _) = self.sb.locate_inode(parent);
+        if !directory {
+            return Ok(parent_group);
+        }
+        let groups = self.sb.block_group_count();
+        let current = self.fs.superblock()?;
+        let average = current.s_free_inodes_count / groups;
+        let mut best: Option<(u32, u16)> = None;
+        for group in 0..groups {
+            let descriptor = self.descriptor(group)?;
+            if descriptor.bg_free_inodes_count == 0
+                || (descriptor.bg_free_inodes_count as u32) < average
+            {
+                continue;
+            }
+            match best {
+                Some((_, free_blocks)) if free_blocks >= descriptor.bg_free_blocks_count => {}
+                _ => best = Some((group, descriptor.bg_free_blocks_count)),
+            }
+        }
+        Ok(best.map(|(group, _)| group).unwrap_or(parent_group))
+    }
+}
+
+impl<'fs, T: disk::WriteDisk + 'fs> Allocator<'fs, T> {
+    /// Claim a single free block, as close after `goal` as possible.
+    pub fn alloc_block(&self, goal: u32) -> io::Result<u32> {
+        self.alloc_blocks(goal, 1).map(|(block, _)| block)
+    }
+
+    /// Claim a run of up to `count` contiguous free blocks, starting as close
+    /// after `goal` as possible.  Returns the first block and the length of
+    /// the run, which may be shorter than requested.
+    ///
+    /// The search starts in the block group containing `goal`, then moves on
+    /// to later groups, wrapping around to the start of the disk.
+    pub fn alloc_blocks(&self, goal: u32, count: u32) -> io::Result<(u32, u32)> {
+        let _guard = self
+            .fs
+            .meta
+            .lock()
+            .expect("Got a poisoned mutex.  Cannot recover");
+        let mut current = self.fs.superblock()?;
+        if current.s_free_blocks_count == 0 || count == 0 {
+            return Err(Ext2Error::NoSpace.into());
+        }
+        let groups = self.sb.block_group_count();
+        let (goal_group, goal_index) =
+            if goal >= self.sb.s_first_data_block && goal < self.sb.s_blocks_count {
+                self.locate_block(goal)?
+            } else {
+                (0, 0)
+            };
+        for i in 0..=groups {
+            let group = (goal_group + i) % groups;
+            let mut descriptor = self.descriptor(group)?;
+            if descriptor.bg_free_blocks_count == 0 {
+                continue;
+            }
+            let mut bitmap =
+                self.read_bitmap(descriptor.bg_block_bitmap, self.blocks_in_group(group))?;
+            // Within the goal group, look after the goal before looking
+            // before it.  The goal group is visited again at the end of
+            // the loop for the blocks before the goal.
+            let start = match i {
+                0 => goal_index,
+                _ => 0,
+            };
+            if let Some((first, len)) = bitmap.find_clear_run(start, count) {
+                descriptor.bg_free_blocks_count = descriptor
+                    .bg_free_blocks_count
+                    .checked_sub(len as u16)
+                    .ok_or_else(|| count_mismatch("free blocks"))?;
+                current.s_free_blocks_count = current
+                    .s_free_blocks_count
+                    .checked_sub(len)
+                    .ok_or_else(|| count_mismatch("free blocks"))?;
+                for bit in first..first + len {
+                    bitmap.set(bit);
+                }
+                self.fs
+                    .write_block(descriptor.bg_block_bitmap, bitmap.as_bytes(), &self.sb)?;
+                self.fs
+                    .write_block_group_descriptor(group, &descriptor, &self.sb)?;
+                self.fs.write_superblock(&current)?;
+                return Ok((self.sb.group_first_block(group) + first, len));
+            }
+        }
+        Err(Ext2Error::NoSpace.into())
+    }
+
+    pub fn free_block(&self, block: u32) -> io::Result<()> {
+        self.free_blocks(block, 1)
+    }
+
+    /// Release `count` contiguous blocks starting at `start`.  The run must
+    /// not cross a block group boundary.
+    pub fn free_blocks(&self, start: u32, count: u32) -> io::Result<()> {
+        let _guard = self
+            .fs
+            .meta
+            .lock()
+            .expect("Got a poisoned mutex.  Cannot recover");
+        if start == 0 {
+            // The pointer of a hole, or the boot block; never a data block.
+            return Err(Ext2Error::corrupt("freeing block 0").into());
+        }
+        let (group, first) = self.locate_block(start)?;
+        if first + count > self.blocks_in_group(group) {
+            return Err(Ext2Error::InvalidInput(format!(
+                "blocks {}..{} cross a block group boundary",
+                start,
+                start + count
+            ))
+            .into());
+        }
+        let mut descriptor = self.descriptor(group)?;
+        let mut current = self.fs.superblock()?;
+        descriptor.bg_free_blocks_count = u16::try_from(count)
+            .ok()
+            .and_then(|count| descriptor.bg_free_blocks_count.checked_add(count))
+            .ok_or_else(|| count_mismatch("free blocks"))?;
+        current.s_free_blocks_count = current
+            .s_free_blocks_count
+            .checked_add(count)
+            .ok_or_else(|| count_mismatch("free blocks"))?;
+        let mut bitmap =
+            self.read_bitmap(descriptor.bg_block_bitmap, self.blocks_in_group(group))?;
+        for bit in first..first + count {
+            if !bitmap.is_set(bit) {
+                let block = self.sb.group_first_block(group) + bit;
+                return Err(Ext2Error::corrupt_block(
+                    "freeing a block that is already free",
+                    block,
+                )
+                .into());
+            }
+            bitmap.clear(bit);
+        }
+        self.fs
+            .write_block(descriptor.bg_block_bitmap, bitmap.as_bytes(), &self.sb)?;
+        self.fs
+            .write_block_group_descriptor(group, &descriptor, &self.sb)?;
+        self.fs.write_superblock(&current)
+    }
+
+    /// Claim a free inode for a new child of directory `parent`.
+    pub fn alloc_inode(&self, parent: u32, directory: bool) -> io::Result<u32> {
+        let _guard = self
+            .fs
+            .meta
+            .lock()
+            .expect("Got a poisoned mutex.  Cannot recover");
+        let mut current = self.fs.superblock()?;
+        if current.s_free_inodes_count == 0 {
+            return Err(Ext2Error::NoSpace.into());
+        }
+        let groups = self.sb.block_group_count();
+        let goal_group = self.inode_group(parent, directory)?;
+        for i in 0..groups {
+            let group = (goal_group + i) % groups;
+            let mut descriptor = self.descriptor(group)?;
+            if descriptor.bg_free_inodes_count == 0 {
+                continue;
+            }
+            let mut bitmap =
+                self.read_bitmap(descriptor.bg_inode_bitmap, self.sb.s_inodes_per_group)?;
+            // Skip the reserved inodes at the start of the first group.
+            let first_usable =
+                (self.sb.first_ino() - 1).saturating_sub(group * self.sb.s_inodes_per_group);
+            if let Some(bit) = bitmap.find_clear(first_usable) {
+                descriptor.bg_free_inodes_count = descriptor
+                    .bg_free_inodes_count
+                    .checked_sub(1)
+                    .ok_or_else(|| count_mismatch("free inodes"))?;
+                current.s_free_inodes_count = current
+                    .s_free_inodes_count
+                    .checked_sub(1)
+                    .ok_or_else(|| count_mismatch("free inodes"))?;
+                if directory {
+                    descriptor.bg_used_dirs_count = descriptor
+                        .bg_used_dirs_count
+                        .checked_add(1)
+                        .ok_or_else(|| count_mismatch("directories"))?;
+                }
+                bitmap.set(bit);
+                self.fs
+                    .write_block(descriptor.bg_inode_bitmap, bitmap.as_bytes(), &self.sb)?;
+                self.fs
+                    .write_block_group_descriptor(group, &descriptor, &self.sb)?;
+                self.fs.write_superblock(&current)?;
+                return Ok(group * self.sb.s_inodes_per_group + bit + 1);
+            }
+        }
+        Err(Ext2Error::NoSpace.into())
+    }
+
+    /// Release inode `inum`.  `directory` must say whether it was allocated
+    /// as a directory, so that the group's directory count stays correct.
+    pub fn free_inode(&self, inum: u32, directory: bool) -> io::Result<()> {
+        let _guard = self
+            .fs
+            .meta
+            .lock()
+            .expect("Got a poisoned mutex.  Cannot recover");
+        if inum == 0 || inum > self.sb.s_inodes_count {
+            return Err(Ext2Error::InvalidInput(format!("no inode {}", inum)).into());
+        }
+        let (group, bit) = self.sb.locate_inode(inum);
+        let mut descriptor = self.descriptor(group)?;
+        let mut bitmap =
+            self.read_bitmap(descriptor.bg_inode_bitmap, self.sb.s_inodes_per_group)?;
+        if inum < self.sb.first_ino() || !bitmap.is_set(bit) {
+            return Err(Ext2Error::corrupt(format!(
+                "freeing inode {}, which is reserved or already free",
+                inum
+            ))
+            .into());
+        }
+        if directory {
+            descriptor.bg_used_dirs_count = descriptor
+                .bg_used_dirs_count
+                .checked_sub(1)
+                .ok_or_else(|| count_mismatch("directories"))?;
+        }
+        descriptor.bg_free_inodes_count = descriptor
+            .bg_free_inodes_count
+            .checked_add(1)
+            .ok_or_else(|| count_mismatch("free inodes"))?;
+        let mut current = self.fs.superblock()?;
+        current.s_free_inodes_count = current
+            .s_free_inodes_count
+            .checked_add(1)
+            .ok_or_else(|| count_mismatch("free inodes"))?;
+        bitmap.clear(bit);
+        self.fs
+            .write_block(descriptor.bg_inode_bitmap, bitmap.as_bytes(), &self.sb)?;
+        self.fs
+            .write_block_group_descriptor(group, &descriptor, &self.sb)?;
+        self.fs.write_superblock(&current)
+    }
+}
+
+/// The error for a count kept on disk that a damaged filesystem has let
+/// fall out of step with its bitmap, so that it would wrap.
+fn count_mismatch(what: &str) -> io::Error {
+    Ext2Error::corrupt(format!(
+        "group or superblock count of {} is out of range",
+        what
+    ))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn scratch() -> Ext2<Cursor<Vec<u8>>> {
+        Ext2::new(Cursor::new(fs::read("./basic.ext2").unwrap())).unwrap()
+    }
+
+    #[test]
+    fn bitmap_runs() {
+        let bitmap = Bitmap::new(vec![0b1100_0111, 0b1111_0000], 14);
+        assert_eq!(bitmap.count_clear(), 7);
+        assert_eq!(bitmap.find_clear(0), Some(3));
+        assert_eq!(bitmap.find_clear(6), Some(8));
+        assert_eq!(bitmap.find_clear_run(0, 8), Some((3, 3)));
+        assert_eq!(bitmap.find_clear_run(7, 8), Some((8, 4)));
+        assert_eq!(bitmap.find_clear_run(8, 2), Some((8, 2)));
+        assert_eq!(bitmap.find_clear(12), None);
+    }
+
+    #[test]
+    fn allocate_contiguous_blocks() {
+        let fs = scratch();
+        let sb = fs.superblock().unwrap();
+        let allocator = fs.allocator().unwrap();
+        assert_eq!(allocator.alloc_blocks(0, 4).unwrap(), (52, 4));
+        assert_eq!(allocator.alloc_block(60).unwrap(), 60);
+        assert_eq!(allocator.alloc_block(60).unwrap(), 61);
+        assert_eq!(allocator.alloc_blocks(60, 8).unwrap(), (62, 2));
+        assert_eq!(allocator.alloc_blocks(60, 8).unwrap(), (56, 4));
+        assert_eq!(
+            allocator.alloc_block(0).unwrap_err().kind(),
+            io::ErrorKind::StorageFull
+        );
+        allocator.free_blocks(53, 2).unwrap();
+        assert!(allocator.free_block(53).is_err());
+        let bitmap = allocator.block_bitmap(0).unwrap();
+        assert_eq!(bitmap.count_clear(), 2);
+        assert!(!bitmap.is_set(54));
+        let descriptor = fs.get_block_group_descriptor(0, &sb).unwrap();
+        assert_eq!(descriptor.bg_free_blocks_count, 2);
+        assert_eq!(fs.superblock().unwrap().s_free_blocks_count, 2);
+    }
+
+    #[test]
+    fn corrupt_numbers_are_errors() {
+        let fs = scratch();
+        let allocator = fs.allocator().unwrap();
+        assert!(allocator.locate_block(64).is_err());
+        assert_eq!(allocator.locate_block(63).unwrap(), (0, 63));
+        let err = allocator.free_block(0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // A superblock claiming fewer free blocks than the bitmap has.
+        let mut sb = fs.superblock().unwrap();
+        sb.s_free_blocks_count = 1;
+        fs.write_superblock(&sb).unwrap();
+        let err = allocator.alloc_blocks(0, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!allocator.block_bitmap(0).unwrap().is_set(52));
+
+        // And ones claiming more free blocks and inodes than there can be.
+        sb.s_free_blocks_count = u32::MAX;
+        sb.s_free_inodes_count = u32::MAX;
+        fs.write_superblock(&sb).unwrap();
+        let err = allocator.free_block(5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(allocator.block_bitmap(0).unwrap().is_set(5));
+        let err = allocator.free_inode(17, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(allocator.inode_bitmap(0).unwrap().is_set(16));
+
+        for &inum in &[0, sb.s_inodes_count + 1] {
+            let err = allocator.free_inode(inum, false).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn allocate_inodes() {
+        let fs = scratch();
+        let sb = fs.superblock().unwrap();
+        let allocator = fs.allocator().unwrap();
+        assert_eq!(allocator.alloc_inode(2, false).unwrap(), 18);
+        assert_eq!(allocator.alloc_inode(2, true).unwrap(), 19);
+        let descriptor = fs.get_block_group_descriptor(0, &sb).unwrap();
+        assert_eq!(descriptor.bg_free_inodes_count, 13);
+        assert_eq!(descriptor.bg_used_dirs_count, 5);
+        allocator.free_inode(19, true).unwrap();
+        assert!(allocator.free_inode(19, true).is_err());
+        assert!(allocator.free_inode(2, true).is_err());
+        let descriptor = fs.get_block_group_descriptor(0, &sb).unwrap();
+        assert_eq!(descriptor.bg_free_inodes_count, 14);
+        assert_eq!(descriptor.bg_used_dirs_count, 4);
+        assert_eq!(fs.superblock().unwrap().s_free_inodes_count, 14);
+        assert!(allocator.inode_bitmap(0).unwrap().is_set(17));
+        assert!(!allocator.inode_bitmap(0).unwrap().is_set(18));
+    }
+}
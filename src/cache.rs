@@ -0,0 +1,338 @@
+//! cache.rs: An LRU cache of disk blocks, to sit between `Ext2` and a slow
+//! disk.
+
+use std::collections::HashMap;
+use std::io;
+
+use super::disk::{ReadDisk, WriteDisk};
+use super::Ext2Error;
+
+/// Marks the ends of the LRU list.
+const NIL: usize = usize::MAX;
+
+/// A write-back LRU cache wrapping any `ReadDisk` or `WriteDisk`.
+///
+/// Sectors are cached in lines of `block_size` bytes, which should match
+/// the block size of the filesystem on the disk.  Writes stay in the cache
+/// until the line is evicted or the disk is synced, so call `Ext2::sync`
+/// (or `BlockCache::into_inner`) before relying on the contents of the
+/// underlying disk.  Dropping the cache writes back whatever it can,
+/// ignoring errors.
+///
+/// ```no_run
+/// # use std::fs::File;
+/// let disk = ext2::BlockCache::new(File::open("basic.ext2").unwrap(), 1024);
+/// let fs = ext2::Ext2::new(disk).unwrap();
+/// fs.open("/hello.txt").unwrap();
+/// println!("{:?}", fs.with_disk(|cache| cache.stats()));
+/// ```
+pub struct BlockCache<D: ReadDisk> {
+    disk: Option<D>,
+    /// How to write back dirty sectors.  Only a `WriteDisk` can be
+    /// written to, so this is filled in by the first write.
+    write_sector: Option<WriteSector<D>>,
+    sector_size: usize,
+    sectors_per_line: u64,
+    capacity: usize,
+    lines: Vec<Line>,
+    /// Line number to its index in `lines`.
+    index: HashMap<u64, usize>,
+    /// Most and least recently used lines.
+    head: usize,
+    tail: usize,
+    stats: CacheStats,
+}
+
+type WriteSector<D> = fn(&mut D, u64, &[u8]) -> io::Result<()>;
+
+struct Line {
+    number: u64,
+    data: Vec<u8>,
+    /// Bit `i` is set when sector `i` of the line has been read or written.
+    valid: u64,
+    /// Bit `i` is set when sector `i` has been written but not written back.
+    dirty: u64,
+    prev: usize,
+    next: usize,
+}
+
+/// Counts of what a `BlockCache` has done, since it was created or the
+/// counts were last reset.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// Sector reads answered from the cache.
+    pub hits: u64,
+    /// Sector reads passed on to the disk.
+    pub misses: u64,
+    /// Lines dropped to make room for others.
+    pub evictions: u64,
+    /// Sectors written back to the disk.
+    pub writebacks: u64,
+}
+
+impl<D: ReadDisk> BlockCache<D> {
+    /// Cache up to `capacity` 4KiB blocks of `disk`, or single sectors if
+    /// its sectors are larger.
+    pub fn new(disk: D, capacity: usize) -> BlockCache<D> {
+        let block_size = disk.sector_size().max(4096);
+        BlockCache::with_block_size(disk, block_size, capacity)
+    }
+
+    /// Cache up to `capacity` blocks of `block_size` bytes, which must be a
+    /// multiple of the disk's sector size, and no more than 64 sectors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` or `capacity` is unusable.
+    pub fn with_block_size(disk: D, block_size: u32, capacity: usize) -> BlockCache<D> {
+        let sector_size = disk.sector_size();
+        assert!(
+            block_size.is_multiple_of(sector_size)
+                && (1..=64).contains(&(block_size / sector_size)),
+            "invalid cache block size {} for {} byte sectors",
+            block_size,
+            sector_size
+        );
+        assert!(capacity > 0, "a cache must hold at least one block");
+        BlockCache {
+            disk: Some(disk),
+            write_sector: None,
+            sector_size: sector_size as usize,
+            sectors_per_line: (block_size / sector_size) as u64,
+            capacity,
+            lines: Vec::new(),
+            index: HashMap::new(),
+            head: NIL,
+            tail: NIL,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// Write back every dirty sector, without syncing the disk itself.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for i in 0..self.lines.len() {
+            self.write_back(i)?;
+        }
+        Ok(())
+    }
+
+    /// Write back every dirty sector and return the underlying disk.
+    pub fn into_inner(mut self) -> io::Result<D> {
+        self.flush()?;
+        Ok(self.disk.take().unwrap())
+    }
+
+    fn disk(&mut self) -> &mut D {
+        self.disk.as_mut().unwrap()
+    }
+
+    /// Find the line for `sector`, making it the most recently used, and
+    /// return its index and the sector's position within it.
+    fn line(&mut self, sector: u64) -> io::Result<(usize, usize)> {
+        let number = sector / self.sectors_per_line;
+        let offset = (sector % self.sectors_per_line) as usize;
+        let i = match self.index.get(&number) {
+            Some(&i) => {
+                self.unlink(i);
+                i
+            }
+            None => self.allocate(number)?,
+        };
+        self.push_front(i);
+        Ok((i, offset))
+    }
+
+    /// Take a free line, or the least recently used one, for `number`.
+    fn allocate(&mut self, number: u64) -> io::Result<usize> {
+        let bytes = self.sectors_per_line as usize * self.sector_size;
+        let i = if self.lines.len() < self.capacity {
+            self.lines.push(Line {
+                number,
+                data: vec![0; bytes],
+                valid: 0,
+                dirty: 0,
+                prev: NIL,
+                next: NIL,
+            });
+            self.lines.len() - 1
+        } else {
+            let i = self.tail;
+            self.write_back(i)?;
+            self.unlink(i);
+            self.index.remove(&self.lines[i].number);
+            self.stats.evictions += 1;
+            let line = &mut self.lines[i];
+            line.number = number;
+            line.valid = 0;
+            i
+        };
+        self.index.insert(number, i);
+        Ok(i)
+    }
+
+    fn write_back(&mut self, i: usize) -> io::Result<()> {
+        let spl = self.sectors_per_line;
+        let ss = self.sector_size;
+        while self.lines[i].dirty != 0 {
+            let offset = self.lines[i].dirty.trailing_zeros() as usize;
+            let sector = self.lines[i].number * spl + offset as u64;
+            let write_sector = self.write_sector.unwrap();
+            let disk = self.disk.as_mut().unwrap();
+            let line = &mut self.lines[i];
+            write_sector(disk, sector, &line.data[offset * ss..(offset + 1) * ss])?;
+            line.dirty &= !(1 << offset);
+            self.stats.writebacks += 1;
+        }
+        Ok(())
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.lines[i].prev, self.lines[i].next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.lines[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.lines[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        self.lines[i].prev = NIL;
+        self.lines[i].next = self.head;
+        match self.head {
+            NIL => self.tail = i,
+            head => self.lines[head].prev = i,
+        }
+        self.head = i;
+    }
+}
+
+impl<D: ReadDisk> ReadDisk for BlockCache<D> {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+        let (i, offset) = self.line(sector)?;
+        let ss = self.sector_size;
+        let range = offset * ss..(offset + 1) * ss;
+        if self.lines[i].valid & 1 << offset == 0 {
+            self.stats.misses += 1;
+            let disk = self.disk.as_mut().unwrap();
+            disk.read_sector(sector, &mut self.lines[i].data[range.clone()])?;
+            self.lines[i].valid |= 1 << offset;
+        } else {
+            self.stats.hits += 1;
+        }
+        let len = buf.len().min(ss);
+        buf[..len].copy_from_slice(&self.lines[i].data[range][..len]);
+        Ok(())
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.sector_size as u32
+    }
+}
+
+impl<D: WriteDisk> WriteDisk for BlockCache<D> {
+    fn write_sector(&mut self, sector: u64, buf: &[u8]) -> io::Result<()> {
+        let ss = self.sector_size;
+        if buf.len() < ss {
+            return Err(Ext2Error::InvalidInput(
+                "cached writes must cover a whole sector".to_owned(),
+            )
+            .into());
+        }
+        self.write_sector = Some(D::write_sector);
+        let (i, offset) = self.line(sector)?;
+        let line = &mut self.lines[i];
+        line.data[offset * ss..(offset + 1) * ss].copy_from_slice(&buf[..ss]);
+        line.valid |= 1 << offset;
+        line.dirty |= 1 << offset;
+        Ok(())
+    }
+
+    fn sync_disk(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.disk().sync_disk()
+    }
+}
+
+impl<D: ReadDisk> Drop for BlockCache<D> {
+    fn drop(&mut self) {
+        if self.disk.is_some() {
+            let _ = self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn disk() -> Cursor<Vec<u8>> {
+        Cursor::new((0..64 * 512).map(|i| (i / 512) as u8).collect())
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let mut cache = BlockCache::with_block_size(disk(), 1024, 4);
+        let mut buf = [0; 512];
+        cache.read_sector(3, &mut buf).unwrap();
+        assert_eq!(buf[0], 3);
+        cache.read_sector(3, &mut buf).unwrap();
+        cache.read_sector(2, &mut buf).unwrap();
+        assert_eq!(buf[0], 2);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                ..CacheStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let mut cache = BlockCache::with_block_size(disk(), 512, 3);
+        let mut buf = [0; 512];
+        for &sector in &[0, 1, 2, 0, 3, 0, 2] {
+            cache.read_sector(sector, &mut buf).unwrap();
+            assert_eq!(buf[0], sector as u8);
+        }
+        // Only 1 was evicted, to make room for 3.
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.hits, 3);
+        cache.read_sector(1, &mut buf).unwrap();
+        assert_eq!(cache.stats().misses, 5);
+    }
+
+    #[test]
+    fn write_back() {
+        let mut cache = BlockCache::with_block_size(disk(), 512, 2);
+        cache.write_sector(5, &[0xaa; 512]).unwrap();
+        let mut buf = [0; 512];
+        cache.read_sector(5, &mut buf).unwrap();
+        assert_eq!(buf[0], 0xaa);
+        assert_eq!(cache.disk().get_ref()[5 * 512], 5);
+
+        // Evicting the line writes it back.
+        cache.read_sector(6, &mut buf).unwrap();
+        cache.read_sector(7, &mut buf).unwrap();
+        assert_eq!(cache.disk().get_ref()[5 * 512], 0xaa);
+        assert_eq!(cache.stats().writebacks, 1);
+
+        cache.write_sector(9, &[0xbb; 512]).unwrap();
+        let disk = cache.into_inner().unwrap();
+        assert_eq!(disk.get_ref()[9 * 512], 0xbb);
+    }
+}
@@ -0,0 +1,649 @@
+//! dir.rs: Adding, removing and renaming directory entries.
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+
+use super::{alloc, disk, now, DirEntry, Ext2, Ext2Error, FileType, Inode, Superblock};
+use super::{IncompatFeatures, EXT2_INDEX_FL};
+
+/// The most hard links an inode may have.
+const EXT2_LINK_MAX: u16 = 32000;
+
+/// Where a directory entry lives on disk.
+struct EntryLocation {
+    block: u32,
+    offset: usize,
+    /// Offset of the preceding entry in the same block, if any.
+    previous: Option<usize>,
+    entry: DirEntry,
+}
+
+impl<T: disk::ReadDisk> Ext2<T> {
+    /// Split `path` into its parent directory, which must exist, and its
+    /// final component.
+    pub(crate) fn parent_dir<'p>(
+        &self,
+        path: &'p Path,
+        sb: &Superblock,
+    ) -> io::Result<(u32, Inode, &'p OsStr)> {
+        self.parent_dir_from(None, path, sb)
+    }
+
+    /// As `parent_dir`, resolving a relative path from directory `base`.
+    pub(crate) fn parent_dir_from<'p>(
+        &self,
+        base: Option<(u32, &Inode)>,
+        path: &'p Path,
+        sb: &Superblock,
+    ) -> io::Result<(u32, Inode, &'p OsStr)> {
+        let (name, parent) = match (path.file_name(), path.parent()) {
+            (Some(name), Some(parent)) => (name, parent),
+            _ => {
+                return Err(
+                    Ext2Error::InvalidInput(format!("{:?} does not name a file", path)).into(),
+                )
+            }
+        };
+        let (dir, dir_inode) = self
+            .lookup_from(base, parent, true, sb)?
+            .ok_or_else(|| not_found(parent))?;
+        if dir_inode.file_type() != FileType::Directory {
+            return Err(not_a_directory(parent));
+        }
+        Ok((dir, dir_inode, name))
+    }
+
+    /// Whether directory `ancestor` is `dir` or one of its ancestors.
+    fn is_ancestor(&self, ancestor: u32, mut dir: u32, sb: &Superblock) -> io::Result<bool> {
+        // Each step moves one level closer to the root, so a loop means the
+        // directory tree is damaged.
+        for _ in 0..sb.s_inodes_count {
+            if dir == ancestor {
+                return Ok(true);
+            }
+            if dir == 2 {
+                return Ok(false);
+            }
+            let inode = self.get_inode(dir, sb)?;
+            dir = self
+                .get_inum_in_dir(&inode, OsStr::new(".."), sb)?
+                .ok_or_else(|| corrupt_dir(dir))?;
+        }
+        Err(corrupt_dir(dir))
+    }
+
+    /// The number of blocks directory `dir_inode` says it has.  A size
+    /// larger than the block map can address, or than the filesystem
+    /// itself, is corrupt.
+    pub(crate) fn dir_block_count(&self, dir_inode: &Inode, sb: &Superblock) -> io::Result<u32> {
+        let blocks = dir_inode.size() / sb.block_size() as u64;
+        let ptrs_per_block = sb.block_size() as u64 / 4;
+        let addressable = 12 + ptrs_per_block + ptrs_per_block.pow(2) + ptrs_per_block.pow(3);
+        if blocks > addressable.min(sb.s_blocks_count as u64) {
+            return Err(Ext2Error::corrupt(format!(
+                "directory of {} bytes is larger than the filesystem",
+                dir_inode.size()
+            ))
+            .into());
+        }
+        Ok(blocks as u32)
+    }
+
+    /// The physical blocks holding the entries of directory `dir_inode`,
+    /// direct and indirect, skipping any holes.
+    pub(crate) fn dir_blocks(&self, dir_inode: &Inode, sb: &Superblock) -> io::Result<Vec<u32>> {
+        let blocks = self.dir_block_count(dir_inode, sb)?;
+        let mut ptrs = Vec::new();
+        for idx in 0..blocks {
+            match self.get_block_ptr(dir_inode, idx, sb)? {
+                0 => {}
+                ptr => ptrs.push(ptr),
+            }
+        }
+        Ok(ptrs)
+    }
+
+    /// Call `f` with each entry of directory `dir_inode`, its block, its
+    /// offset within the block, and the offset of the entry before it, until
+    /// `f` returns true.  Returns whether `f` ever did.
+    fn scan_dir<F>(&self, dir_inode: &Inode, sb: &Superblock, mut f: F) -> io::Result<bool>
+    where
+        F: FnMut(&[u8], EntryLocation) -> io::Result<bool>,
+    {
+        let bs = sb.block_size() as usize;
+        let mut buf = vec![0; bs];
+        for block in self.dir_blocks(dir_inode, sb)? {
+            self.read_block(block, &mut buf, sb)?;
+            let mut offset: usize = 0;
+            let mut previous = None;
+            while offset < bs {
+                let entry = DirEntry::checked(&buf[offset..], block)?;
+                let rec_len = entry.rec_len as usize;
+                let location = EntryLocation {
+                    block,
+                    offset,
+                    previous,
+                    entry,
+                };
+                if f(&buf, location)? {
+                    return Ok(true);
+                }
+                previous = Some(offset);
+                offset += rec_len;
+            }
+        }
+        Ok(false)
+    }
+
+    fn find_dir_entry(
+        &self,
+        dir_inode: &Inode,
+        name: &OsStr,
+        sb: &Superblock,
+    ) -> io::Result<Option<EntryLocation>> {
+        let mut found = None;
+        self.scan_dir(dir_inode, sb, |_, location| {
+            if location.entry.inode != 0 && location.entry.name == name {
+                found = Some(location);
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })?;
+        Ok(found)
+    }
+
+    /// Whether a directory holds nothing but `.` and `..`.
+    fn is_empty_dir(&self, dir_inode: &Inode, sb: &Superblock) -> io::Result<bool> {
+        let occupied = self.scan_dir(dir_inode, sb, |_, location| {
+            let entry = location.entry;
+            Ok(entry.inode != 0 && entry.name != "." && entry.name != "..")
+        })?;
+        Ok(!occupied)
+    }
+
+    /// Check that inode `inum` can be released, so that a damaged inode
+    /// is found before its last directory entry is removed rather than
+    /// after, when it would be left allocated but unreachable.
+    fn check_release(&self, inum: u32, inode: &Inode, sb: &Superblock) -> io::Result<()> {
+        let (group, bit) = sb.locate_inode(inum);
+        let allocator = alloc::Allocator::new(self, sb);
+        if inum < sb.first_ino() || !allocator.inode_bitmap(group)?.is_set(bit) {
+            return Err(
+                Ext2Error::corrupt(format!("inode {} is reserved or already free", inum)).into(),
+            );
+        }
+        if inode.has_block_map(sb) {
+            for &ptr in inode.i_block.0.iter() {
+                self.check_block_tree(ptr, 0, sb)?;
+            }
+            self.check_block_tree(inode.i_block.1, 1, sb)?;
+            self.check_block_tree(inode.i_block.2, 2, sb)?;
+            self.check_block_tree(inode.i_block.3, 3, sb)?;
+        }
+        Ok(())
+    }
+
+    fn new_entry(&self, name: &OsStr, inum: u32, file_type: FileType, sb: &Superblock) -> DirEntry {
+        DirEntry {
+            inode: inum,
+            rec_len: 0,
+            name_len: name.len() as u8,
+            file_type: if sb.incompat_features().contains(IncompatFeatures::FILETYPE) {
+                file_type as u8
+            } else {
+                0
+            },
+            name: name.to_os_string(),
+        }
+    }
+}
+
+impl<T: disk::WriteDisk> Ext2<T> {
+    /// Create a new, empty directory at `path`.
+    pub fn create_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let sb = self.superblock()?;
+        let (parent, mut parent_inode, name) = self.parent_dir(path, &sb)?;
+        if self.get_inum_in_dir(&parent_inode, name, &sb)?.is_some() {
+            return Err(already_exists(path));
+        }
+        if parent_inode.i_links_count >= EXT2_LINK_MAX {
+            return Err(too_many_links(path));
+        }
+        let inum = alloc::Allocator::new(self, &sb).alloc_inode(parent, true)?;
+        let time = now();
+        let mut inode = Inode {
+            i_mode: 0x4000 | 0o755,
+            i_atime: time,
+            i_ctime: time,
+            i_mtime: time,
+            i_links_count: 2,
+            ..Inode::default()
+        };
+        self.init_dir(inum, &mut inode, parent, &sb)
+            .and_then(|()| {
+                parent_inode.i_links_count += 1;
+                self.add_dir_entry(
+                    &mut parent_inode,
+                    parent,
+                    name,
+                    inum,
+                    FileType::Directory,
+                    &sb,
+                )
+            })
+            .map_err(|err| self.abandon_inode(inum, &mut inode, &sb, err))
+    }
+
+    /// Give the new directory `inum` its first block, holding `.` and
+    /// `..`, and write its inode.
+    fn init_dir(
+        &self,
+        inum: u32,
+        inode: &mut Inode,
+        parent: u32,
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        let bs = sb.block_size() as usize;
+        let goal = sb.group_first_block(sb.locate_inode(inum).0);
+        let (block, _) = self.map_block(inode, 0, goal, sb)?;
+        let mut buf = vec![0; bs];
+        let dot = self.new_entry(OsStr::new("."), inum, FileType::Directory, sb);
+        let dotdot = self.new_entry(OsStr::new(".."), parent, FileType::Directory, sb);
+        DirEntry { rec_len: 12, ..dot }.write_to(&mut buf);
+        DirEntry {
+            rec_len: (bs - 12) as u32,
+            ..dotdot
+        }
+        .write_to(&mut buf[12..]);
+        self.write_block(block, &buf, sb)?;
+        inode.set_size(bs as u64);
+        self.write_new_inode(inum, inode, sb)
+    }
+
+    /// Remove the file at `path`.  The file's blocks and inode are released
+    /// once its last link is gone.
+    pub fn remove_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let sb = self.superblock()?;
+        let (parent, mut parent_inode, name) = self.parent_dir(path, &sb)?;
+        let location = self.find_dir_entry(&parent_inode, name, &sb)?;
+        let location = location.ok_or_else(|| not_found(path))?;
+        let inum = location.entry.inode;
+        let mut inode = self.get_inode(inum, &sb)?;
+        if inode.file_type() == FileType::Directory {
+            return Err(Ext2Error::IsADirectory(path.to_owned()).into());
+        }
+        if inode.i_links_count <= 1 {
+            self.check_release(inum, &inode, &sb)?;
+        }
+        self.remove_dir_entry(&mut parent_inode, parent, location, &sb)?;
+        self.drop_link(inum, &mut inode, &sb)
+    }
+
+    /// Remove the empty directory at `path`.
+    pub fn remove_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let sb = self.superblock()?;
+        let (parent, mut parent_inode, name) = self.parent_dir(path, &sb)?;
+        if name == "." || name == ".." {
+            return Err(Ext2Error::InvalidInput(format!("cannot remove {:?}", path)).into());
+        }
+        let location = self.find_dir_entry(&parent_inode, name, &sb)?;
+        let location = location.ok_or_else(|| not_found(path))?;
+        let inum = location.entry.inode;
+        let mut inode = self.get_inode(inum, &sb)?;
+        if inode.file_type() != FileType::Directory {
+            return Err(not_a_directory(path));
+        }
+        if !self.is_empty_dir(&inode, &sb)? {
+            return Err(Ext2Error::DirectoryNotEmpty(path.to_owned()).into());
+        }
+        self.check_release(inum, &inode, &sb)?;
+        if parent_inode.i_links_count == 0 {
+            return Err(no_links(parent));
+        }
+        self.remove_dir_entry(&mut parent_inode, parent, location, &sb)?;
+        self.drop_dir(inum, &mut inode, parent, &sb)
+    }
+
+    /// Create a new link at `link` to the existing file at `original`.
+    pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        original: P,
+        link: Q,
+    ) -> io::Result<()> {
+        let (original, link) = (original.as_ref(), link.as_ref());
+        let sb = self.superblock()?;
+        let (inum, mut inode) = self
+            .lookup_link(original, &sb)?
+            .ok_or_else(|| not_found(original))?;
+        if inode.file_type() == FileType::Directory {
+            return Err(Ext2Error::PermissionDenied(format!(
+                "cannot hard link directory {:?}",
+                original
+            ))
+            .into());
+        }
+        if inode.i_links_count >= EXT2_LINK_MAX {
+            return Err(too_many_links(original));
+        }
+        let (parent, mut parent_inode, name) = self.parent_dir(link, &sb)?;
+        if self.get_inum_in_dir(&parent_inode, name, &sb)?.is_some() {
+            return Err(already_exists(link));
+        }
+        let file_type = inode.file_type();
+        self.add_dir_entry(&mut parent_inode, parent, name, inum, file_type, &sb)?;
+        inode.i_links_count += 1;
+        inode.i_ctime = now();
+        self.write_inode(inum, &inode, &sb)
+    }
+
+    /// Rename the file or directory at `from` to `to`, replacing whatever
+    /// was at `to`.
+    ///
+    /// As with `rename(2)`, a file may only replace a file and a directory
+    /// may only replace an empty directory.
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> io::Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let sb = self.superblock()?;
+        let (old_parent, old_parent_inode, old_name) = self.parent_dir(from, &sb)?;
+        if old_name == "." || old_name == ".." {
+            return Err(Ext2Error::InvalidInput(format!("cannot rename {:?}", from)).into());
+        }
+        let inum = self
+            .get_inum_in_dir(&old_parent_inode, old_name, &sb)?
+            .ok_or_else(|| not_found(from))?;
+        let mut inode = self.get_inode(inum, &sb)?;
+        let file_type = inode.file_type();
+        let is_dir = file_type == FileType::Directory;
+        let (new_parent, mut new_parent_inode, new_name) = self.parent_dir(to, &sb)?;
+        if is_dir && self.is_ancestor(inum, new_parent, &sb)? {
+            return Err(
+                Ext2Error::InvalidInput(format!("cannot move {:?} inside itself", from)).into(),
+            );
+        }
+
+        match self.find_dir_entry(&new_parent_inode, new_name, &sb)? {
+            Some(ref location) if location.entry.inode == inum => return Ok(()),
+            Some(location) => {
+                let target = location.entry.inode;
+                let mut target_inode = self.get_inode(target, &sb)?;
+                let target_is_dir = target_inode.file_type() == FileType::Directory;
+                if is_dir && !target_is_dir {
+                    return Err(not_a_directory(to));
+                }
+                if !is_dir && target_is_dir {
+                    return Err(Ext2Error::IsADirectory(to.to_owned()).into());
+                }
+                if target_is_dir && !self.is_empty_dir(&target_inode, &sb)? {
+                    return Err(Ext2Error::DirectoryNotEmpty(to.to_owned()).into());
+                }
+                if target_is_dir || target_inode.i_links_count <= 1 {
+                    self.check_release(target, &target_inode, &sb)?;
+                }
+                if target_is_dir && new_parent_inode.i_links_count == 0 {
+                    return Err(no_links(new_parent));
+                }
+                let entry = self.new_entry(new_name, inum, file_type, &sb);
+                self.replace_dir_entry(&mut new_parent_inode, new_parent, location, entry, &sb)?;
+                if target_is_dir {
+                    self.drop_dir(target, &mut target_inode, new_parent, &sb)?;
+                } else {
+                    self.drop_link(target, &mut target_inode, &sb)?;
+                }
+            }
+            None => {
+                if is_dir
+                    && old_parent != new_parent
+                    && new_parent_inode.i_links_count >= EXT2_LINK_MAX
+                {
+                    return Err(too_many_links(to));
+                }
+                self.add_dir_entry(
+                    &mut new_parent_inode,
+                    new_parent,
+                    new_name,
+                    inum,
+                    file_type,
+                    &sb,
+                )?;
+            }
+        }
+
+        // The parents may be the same directory, and were changed above, so
+        // read them afresh.
+        let mut old_parent_inode = self.get_inode(old_parent, &sb)?;
+        let location = self
+            .find_dir_entry(&old_parent_inode, old_name, &sb)?
+            .ok_or_else(|| not_found(from))?;
+        self.remove_dir_entry(&mut old_parent_inode, old_parent, location, &sb)?;
+        if is_dir && old_parent != new_parent {
+            let location = self
+                .find_dir_entry(&inode, OsStr::new(".."), &sb)?
+                .ok_or_else(|| corrupt_dir(inum))?;
+            let dotdot = self.new_entry(OsStr::new(".."), new_parent, FileType::Directory, &sb);
+            self.replace_dir_entry(&mut inode, inum, location, dotdot, &sb)?;
+            old_parent_inode.i_links_count = old_parent_inode
+                .i_links_count
+                .checked_sub(1)
+                .ok_or_else(|| no_links(old_parent))?;
+            self.write_inode(old_parent, &old_parent_inode, &sb)?;
+            let mut new_parent_inode = self.get_inode(new_parent, &sb)?;
+            new_parent_inode.i_links_count += 1;
+            self.write_inode(new_parent, &new_parent_inode, &sb)?;
+        }
+        inode.i_ctime = now();
+        self.write_inode(inum, &inode, &sb)
+    }
+
+    /// Link inode `inum` into directory `dir` under `name`.
+    ///
+    /// The entry goes into the first gap large enough to hold it, splitting
+    /// the slack off the end of an existing record.  If no block has room the
+    /// directory grows by one block.
+    pub(crate) fn add_dir_entry(
+        &self,
+        dir_inode: &mut Inode,
+        dir: u32,
+        name: &OsStr,
+        inum: u32,
+        file_type: FileType,
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        if name.len() > 255 {
+            return Err(
+                Ext2Error::InvalidInput(format!("{:?} is longer than 255 bytes", name)).into(),
+            );
+        }
+        let mut entry = self.new_entry(name, inum, file_type, sb);
+        let needed = entry.min_rec_len();
+        let mut gap = None;
+        self.scan_dir(dir_inode, sb, |buf, location| {
+            let existing = &location.entry;
+            let used = if existing.inode == 0 {
+                0
+            } else {
+                existing.min_rec_len()
+            };
+            if existing.rec_len as usize >= used + needed {
+                gap = Some((buf.to_vec(), location, used));
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })?;
+        if let Some((mut buf, location, used)) = gap {
+            if used > 0 {
+                let shrunk = DirEntry {
+                    rec_len: used as u32,
+                    ..location.entry.clone()
+                };
+                shrunk.write_to(&mut buf[location.offset..]);
+            }
+            entry.rec_len = (location.entry.rec_len as usize - used) as u32;
+            entry.write_to(&mut buf[location.offset + used..]);
+            self.write_block(location.block, &buf, sb)?;
+            return self.touch_dir(dir_inode, dir, sb);
+        }
+
+        let bs = sb.block_size() as usize;
+        let blocks = (dir_inode.size() / bs as u64) as u32;
+        let goal = sb.group_first_block(sb.locate_inode(dir).0);
+        let (block, _) = self.map_block(dir_inode, blocks, goal, sb)?;
+        let mut buf = vec![0; bs];
+        entry.rec_len = bs as u32;
+        entry.write_to(&mut buf);
+        self.write_block(block, &buf, sb)?;
+        dir_inode.set_size((blocks as u64 + 1) * bs as u64);
+        self.touch_dir(dir_inode, dir, sb)
+    }
+
+    /// Remove the entry at `location` from directory `dir`, folding its space
+    /// into the entry before it.
+    fn remove_dir_entry(
+        &self,
+        dir_inode: &mut Inode,
+        dir: u32,
+        location: EntryLocation,
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        let mut buf = vec![0; sb.block_size() as usize];
+        self.read_block(location.block, &mut buf, sb)?;
+        match location.previous {
+            Some(previous) => {
+                let mut entry = DirEntry::checked(&buf[previous..], location.block)?;
+                entry.rec_len =
+                    (location.offset - previous + location.entry.rec_len as usize) as u32;
+                entry.write_to(&mut buf[previous..]);
+            }
+            None => {
+                // The first entry of a block has nothing to merge into, so
+                // it is kept as an unused record.
+                let entry = DirEntry {
+                    inode: 0,
+                    ..location.entry
+                };
+                entry.write_to(&mut buf[location.offset..]);
+            }
+        }
+        self.write_block(location.block, &buf, sb)?;
+        self.touch_dir(dir_inode, dir, sb)
+    }
+
+    /// Point the entry at `location` in directory `dir` at a different inode.
+    /// `entry` must fit in the existing record.
+    fn replace_dir_entry(
+        &self,
+        dir_inode: &mut Inode,
+        dir: u32,
+        location: EntryLocation,
+        entry: DirEntry,
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        let mut buf = vec![0; sb.block_size() as usize];
+        self.read_block(location.block, &mut buf, sb)?;
+        let entry = DirEntry {
+            rec_len: location.entry.rec_len,
+            ..entry
+        };
+        entry.write_to(&mut buf[location.offset..]);
+        self.write_block(location.block, &buf, sb)?;
+        self.touch_dir(dir_inode, dir, sb)
+    }
+
+    /// Record a modification of directory `dir` and write its inode back.
+    ///
+    /// Any hashed index is dropped, since this crate does not maintain it.
+    fn touch_dir(&self, dir_inode: &mut Inode, dir: u32, sb: &Superblock) -> io::Result<()> {
+        dir_inode.i_flags &= !EXT2_INDEX_FL;
+        dir_inode.i_mtime = now();
+        dir_inode.i_ctime = dir_inode.i_mtime;
+        self.write_inode(dir, dir_inode, sb)
+    }
+
+    /// Remove one link to the non-directory `inum`, releasing it if that was
+    /// the last.
+    fn drop_link(&self, inum: u32, inode: &mut Inode, sb: &Superblock) -> io::Result<()> {
+        inode.i_links_count = inode.i_links_count.saturating_sub(1);
+        inode.i_ctime = now();
+        if inode.i_links_count > 0 {
+            self.write_inode(inum, inode, sb)
+        } else {
+            self.release_inode(inum, inode, sb)
+        }
+    }
+
+    /// Release the empty directory `inum`, whose entry has already been
+    /// removed from `parent`.
+    fn drop_dir(
+        &self,
+        inum: u32,
+        inode: &mut Inode,
+        parent: u32,
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        // The directory's ".." no longer refers to its parent.
+        let mut parent_inode = self.get_inode(parent, sb)?;
+        parent_inode.i_links_count = parent_inode
+            .i_links_count
+            .checked_sub(1)
+            .ok_or_else(|| no_links(parent))?;
+        inode.i_links_count = 0;
+        self.release_inode(inum, inode, sb)?;
+        self.write_inode(parent, &parent_inode, sb)
+    }
+
+    /// Undo the creation of inode `inum` after `err`, freeing it and any
+    /// blocks it was given, and return `err`.  Undoing is best effort: the
+    /// first error is the one to report.
+    pub(crate) fn abandon_inode(
+        &self,
+        inum: u32,
+        inode: &mut Inode,
+        sb: &Superblock,
+        err: io::Error,
+    ) -> io::Error {
+        inode.i_links_count = 0;
+        let _ = self.release_inode(inum, inode, sb);
+        err
+    }
+
+    /// Free the blocks and the inode of a file with no remaining links.
+    fn release_inode(&self, inum: u32, inode: &mut Inode, sb: &Superblock) -> io::Result<()> {
+        let is_dir = inode.file_type() == FileType::Directory;
+        if inode.has_block_map(sb) {
+            self.free_inode_blocks(inode, sb)?;
+        }
+        inode.set_size(0);
+        inode.i_dtime = now();
+        self.write_inode(inum, inode, sb)?;
+        alloc::Allocator::new(self, sb).free_inode(inum, is_dir)
+    }
+}
+
+pub(crate) fn not_found(path: &Path) -> io::Error {
+    Ext2Error::NotFound(path.to_owned()).into()
+}
+
+fn not_a_directory(path: &Path) -> io::Error {
+    Ext2Error::NotADirectory(path.to_owned()).into()
+}
+
+pub(crate) fn already_exists(path: &Path) -> io::Error {
+    Ext2Error::AlreadyExists(path.to_owned()).into()
+}
+
+fn too_many_links(path: &Path) -> io::Error {
+    Ext2Error::TooManyLinks(path.to_owned()).into()
+}
+
+fn no_links(inum: u32) -> io::Error {
+    Ext2Error::corrupt(format!("directory {} has a link count of 0", inum)).into()
+}
+
+fn corrupt_dir(inum: u32) -> io::Error {
+    Ext2Error::corrupt(format!("directory {} has no valid \"..\" entry", inum)).into()
+}
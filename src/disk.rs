@@ -1,15 +1,105 @@
 use std::cmp;
+use std::fs::File;
 use std::io::{self, prelude::*};
+use std::sync::Arc;
 
-pub trait Disk {
+use super::Ext2Error;
+
+/// Somewhere an ext2 filesystem can be read from.
+///
+/// Anything `Read + Seek` is a `ReadDisk` with 512-byte sectors.  An
+/// `Ext2` on a disk that is not also a `WriteDisk` has none of the methods
+/// that modify the filesystem.
+pub trait ReadDisk {
     fn read_sector(&mut self, blocknum: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    /// The size in bytes of the sectors `read_sector` and `write_sector`
+    /// transfer: a power of two no smaller than 512.
+    fn sector_size(&self) -> u32 {
+        512
+    }
+
+    /// Fill `buf`, a whole number of sectors long, from consecutive
+    /// sectors starting at `start`.
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        let ss = self.sector_size();
+        check_whole_sectors(buf.len(), ss)?;
+        for (i, sector) in buf.chunks_mut(ss as usize).enumerate() {
+            self.read_sector(start + i as u64, sector)?;
+        }
+        Ok(())
+    }
+
+    /// Fill each of `bufs` in turn from consecutive sectors starting at
+    /// `start`, as if they were one buffer.
+    fn read_sectors_vectored(&mut self, start: u64, bufs: &mut [&mut [u8]]) -> io::Result<()> {
+        let mut sector = start;
+        for buf in bufs {
+            self.read_sectors(sector, buf)?;
+            sector += buf.len() as u64 / self.sector_size() as u64;
+        }
+        Ok(())
+    }
+
+    /// Whether `read_sectors_at` works, so that many threads can read the
+    /// disk at once.
+    fn can_read_at(&self) -> bool {
+        false
+    }
+
+    /// Like `read_sectors`, but through a shared reference, in the manner
+    /// of `pread`.  Only called when `can_read_at` says it works.
+    fn read_sectors_at(&self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        let _ = (start, buf);
+        Err(Ext2Error::Unsupported("positional reads".to_owned()).into())
+    }
+}
+
+/// A `ReadDisk` that can be written to as well.
+///
+/// Anything `Read + Write + Seek` is a `WriteDisk`.
+pub trait WriteDisk: ReadDisk {
     fn write_sector(&mut self, blocknum: u64, buf: &[u8]) -> io::Result<()>;
     fn sync_disk(&mut self) -> io::Result<()>;
+
+    /// Write `buf`, a whole number of sectors long, to consecutive sectors
+    /// starting at `start`.
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> io::Result<()> {
+        let ss = self.sector_size();
+        check_whole_sectors(buf.len(), ss)?;
+        for (i, sector) in buf.chunks(ss as usize).enumerate() {
+            self.write_sector(start + i as u64, sector)?;
+        }
+        Ok(())
+    }
+
+    /// Write each of `bufs` in turn to consecutive sectors starting at
+    /// `start`, as if they were one buffer.
+    fn write_sectors_vectored(&mut self, start: u64, bufs: &[&[u8]]) -> io::Result<()> {
+        let mut sector = start;
+        for buf in bufs {
+            self.write_sectors(sector, buf)?;
+            sector += buf.len() as u64 / self.sector_size() as u64;
+        }
+        Ok(())
+    }
 }
 
-impl<T> Disk for T
+fn check_whole_sectors(len: usize, sector_size: u32) -> io::Result<()> {
+    if len.is_multiple_of(sector_size as usize) {
+        Ok(())
+    } else {
+        Err(Ext2Error::InvalidInput(format!(
+            "buffer of {} bytes is not a whole number of sectors",
+            len
+        ))
+        .into())
+    }
+}
+
+impl<T> ReadDisk for T
 where
-    T: Read + Write + Seek,
+    T: Read + Seek,
 {
     fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
         let len = cmp::min(buf.len(), 512);
@@ -17,6 +107,28 @@ where
         self.read_exact(&mut buf[..len])
     }
 
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        check_whole_sectors(buf.len(), 512)?;
+        self.seek(io::SeekFrom::Start(512 * start))?;
+        self.read_exact(buf)
+    }
+
+    fn read_sectors_vectored(&mut self, start: u64, bufs: &mut [&mut [u8]]) -> io::Result<()> {
+        for buf in bufs.iter() {
+            check_whole_sectors(buf.len(), 512)?;
+        }
+        self.seek(io::SeekFrom::Start(512 * start))?;
+        for buf in bufs {
+            self.read_exact(buf)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> WriteDisk for T
+where
+    T: Read + Write + Seek,
+{
     fn write_sector(&mut self, sector: u64, buf: &[u8]) -> io::Result<()> {
         let len = cmp::min(buf.len(), 512);
         self.seek(io::SeekFrom::Start(512 * sector))?;
@@ -26,6 +138,265 @@ where
     fn sync_disk(&mut self) -> io::Result<()> {
         self.flush()
     }
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> io::Result<()> {
+        check_whole_sectors(buf.len(), 512)?;
+        self.seek(io::SeekFrom::Start(512 * start))?;
+        self.write_all(buf)
+    }
+
+    fn write_sectors_vectored(&mut self, start: u64, bufs: &[&[u8]]) -> io::Result<()> {
+        for buf in bufs {
+            check_whole_sectors(buf.len(), 512)?;
+        }
+        self.seek(io::SeekFrom::Start(512 * start))?;
+        for buf in bufs {
+            self.write_all(buf)?;
+        }
+        Ok(())
+    }
+}
+
+/// A disk with sectors of some size other than 512 bytes, such as a
+/// 4K-native drive or an image of one.
+pub struct SectorDisk<T> {
+    inner: T,
+    sector_size: u32,
+}
+
+impl<T> SectorDisk<T> {
+    /// # Panics
+    ///
+    /// Panics if `sector_size` is not a power of two of at least 512.
+    pub fn new(inner: T, sector_size: u32) -> SectorDisk<T> {
+        assert!(
+            sector_size.is_power_of_two() && sector_size >= 512,
+            "invalid sector size {}",
+            sector_size
+        );
+        SectorDisk { inner, sector_size }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> ReadDisk for SectorDisk<T>
+where
+    T: Read + Seek,
+{
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+        let len = cmp::min(buf.len(), self.sector_size as usize);
+        self.inner
+            .seek(io::SeekFrom::Start(self.sector_size as u64 * sector))?;
+        self.inner.read_exact(&mut buf[..len])
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        check_whole_sectors(buf.len(), self.sector_size)?;
+        self.inner
+            .seek(io::SeekFrom::Start(self.sector_size as u64 * start))?;
+        self.inner.read_exact(buf)
+    }
+}
+
+impl<T> WriteDisk for SectorDisk<T>
+where
+    T: Read + Write + Seek,
+{
+    fn write_sector(&mut self, sector: u64, buf: &[u8]) -> io::Result<()> {
+        let len = cmp::min(buf.len(), self.sector_size as usize);
+        self.inner
+            .seek(io::SeekFrom::Start(self.sector_size as u64 * sector))?;
+        self.inner.write_all(&buf[..len])
+    }
+
+    fn sync_disk(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> io::Result<()> {
+        check_whole_sectors(buf.len(), self.sector_size)?;
+        self.inner
+            .seek(io::SeekFrom::Start(self.sector_size as u64 * start))?;
+        self.inner.write_all(buf)
+    }
+}
+
+/// Reads that leave any cursor alone, in the manner of `pread`, so that one
+/// source can serve many threads at once.
+pub trait ReadAt {
+    /// Fill `buf` from `offset`, failing with `UnexpectedEof` if there is
+    /// not enough data.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+}
+
+impl ReadAt for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let start = cmp::min(offset, self.len() as u64) as usize;
+        match self[start..].get(..buf.len()) {
+            Some(data) => {
+                buf.copy_from_slice(data);
+                Ok(())
+            }
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self[..].read_exact_at(buf, offset)
+    }
+}
+
+impl<R: ReadAt + ?Sized> ReadAt for &R {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+}
+
+impl<R: ReadAt + ?Sized> ReadAt for Arc<R> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+}
+
+/// A read-only disk on anything `ReadAt`, which `Ext2` reads without
+/// taking a lock, so that threads sharing it read in parallel.
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # use std::sync::Arc;
+/// let disk = ext2::ReadAtDisk::new(File::open("basic.ext2").unwrap());
+/// let fs = Arc::new(ext2::Ext2::new(disk).unwrap());
+/// let threads: Vec<_> = ["/hello.txt", "/goodbye.txt"]
+///     .iter()
+///     .map(|&path| {
+///         let fs = fs.clone();
+///         std::thread::spawn(move || fs.open(path).unwrap().size())
+///     })
+///     .collect();
+/// ```
+pub struct ReadAtDisk<R> {
+    inner: R,
+    sector_size: u32,
+}
+
+impl<R: ReadAt> ReadAtDisk<R> {
+    pub fn new(inner: R) -> ReadAtDisk<R> {
+        ReadAtDisk::with_sector_size(inner, 512)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `sector_size` is not a power of two of at least 512.
+    pub fn with_sector_size(inner: R, sector_size: u32) -> ReadAtDisk<R> {
+        assert!(
+            sector_size.is_power_of_two() && sector_size >= 512,
+            "invalid sector size {}",
+            sector_size
+        );
+        ReadAtDisk { inner, sector_size }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: ReadAt> ReadDisk for ReadAtDisk<R> {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+        let len = cmp::min(buf.len(), self.sector_size as usize);
+        self.inner
+            .read_exact_at(&mut buf[..len], self.sector_size as u64 * sector)
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.read_sectors_at(start, buf)
+    }
+
+    fn can_read_at(&self) -> bool {
+        true
+    }
+
+    fn read_sectors_at(&self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        check_whole_sectors(buf.len(), self.sector_size)?;
+        self.inner
+            .read_exact_at(buf, self.sector_size as u64 * start)
+    }
+}
+
+/// Hides the writing half of a disk, so that an `Ext2` on it is read-only
+/// at the type level.
+///
+/// ```compile_fail
+/// # use std::fs::File;
+/// let disk = ext2::ReadOnlyDisk::new(File::open("basic.ext2").unwrap());
+/// let fs = ext2::Ext2::new(disk).unwrap();
+/// fs.create("/new").unwrap();
+/// ```
+pub struct ReadOnlyDisk<D> {
+    inner: D,
+}
+
+impl<D: ReadDisk> ReadOnlyDisk<D> {
+    pub fn new(inner: D) -> ReadOnlyDisk<D> {
+        ReadOnlyDisk { inner }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: ReadDisk> ReadDisk for ReadOnlyDisk<D> {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_sector(sector, buf)
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.sector_size()
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_sectors(start, buf)
+    }
+
+    fn read_sectors_vectored(&mut self, start: u64, bufs: &mut [&mut [u8]]) -> io::Result<()> {
+        self.inner.read_sectors_vectored(start, bufs)
+    }
+
+    fn can_read_at(&self) -> bool {
+        self.inner.can_read_at()
+    }
+
+    fn read_sectors_at(&self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_sectors_at(start, buf)
+    }
 }
 
 #[cfg(test)]
@@ -88,4 +459,87 @@ mod test {
         c.sync_disk().unwrap();
         assert_eq!(&c.into_inner()[510..514], &[255, 255, 8, 8]);
     }
+
+    /// A disk with only the required methods, to exercise the defaults.
+    struct Sectors(Cursor<Vec<u8>>);
+
+    impl ReadDisk for Sectors {
+        fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+            self.0.read_sector(sector, buf)
+        }
+    }
+
+    impl WriteDisk for Sectors {
+        fn write_sector(&mut self, sector: u64, buf: &[u8]) -> io::Result<()> {
+            self.0.write_sector(sector, buf)
+        }
+
+        fn sync_disk(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let mut plain = Cursor::new(make_vec(4096));
+        let mut defaults = Sectors(Cursor::new(make_vec(4096)));
+        let disks: [&mut dyn WriteDisk; 2] = [&mut plain, &mut defaults];
+        for disk in disks {
+            let mut buf = [0; 1024];
+            disk.read_sectors(1, &mut buf).unwrap();
+            assert_eq!((buf[0], buf[1023]), (8, 23));
+
+            let (mut a, mut b) = ([0; 512], [0; 1024]);
+            disk.read_sectors_vectored(2, &mut [&mut a, &mut b])
+                .unwrap();
+            assert_eq!((a[0], b[0], b[1023]), (16, 24, 39));
+
+            disk.write_sectors(6, &[1; 1024]).unwrap();
+            disk.write_sectors_vectored(4, &[&[2; 512], &[3; 512]])
+                .unwrap();
+            let mut buf = [0; 2048];
+            disk.read_sectors(4, &mut buf).unwrap();
+            assert_eq!((buf[0], buf[512], buf[1024], buf[2047]), (2, 3, 1, 1));
+
+            let err = disk.read_sectors(0, &mut [0; 100]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let err = disk.read_sectors(7, &mut buf).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn positional() {
+        let data = make_vec(4096);
+        let mut disk = ReadAtDisk::new(&data[..]);
+        assert!(disk.can_read_at());
+        let mut buf = [0; 1024];
+        disk.read_sectors_at(3, &mut buf).unwrap();
+        assert_eq!((buf[0], buf[1023]), (24, 39));
+        disk.read_sector(7, &mut buf).unwrap();
+        assert_eq!(buf[0], 56);
+        let err = disk.read_sectors_at(7, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let plain = Cursor::new(data);
+        assert!(!plain.can_read_at());
+        let err = plain.read_sectors_at(0, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn large_sectors() {
+        let mut disk = SectorDisk::new(Cursor::new(make_vec(16384)), 4096);
+        assert_eq!(disk.sector_size(), 4096);
+        let mut buf = [0; 4096];
+        disk.read_sector(2, &mut buf).unwrap();
+        assert_eq!((buf[0], buf[4095]), (128, 191));
+
+        disk.write_sectors_vectored(1, &[&[1; 4096], &[2; 4096]])
+            .unwrap();
+        let err = disk.write_sectors(0, &[0; 512]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let data = disk.into_inner().into_inner();
+        assert_eq!((data[4095], data[4096], data[8192]), (63, 1, 2));
+    }
 }
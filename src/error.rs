@@ -0,0 +1,151 @@
+//! error.rs: The errors this crate reports.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong reading or writing a filesystem.
+///
+/// The public API returns `io::Result`, to fit alongside `std::fs`; errors
+/// raised by this crate carry an `Ext2Error` inside, which `Ext2Error::of`
+/// recovers.
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # let fs = File::open("basic.ext2").and_then(ext2::Ext2::new).unwrap();
+/// if let Err(err) = fs.open("/etc/passwd") {
+///     match ext2::Ext2Error::of(&err) {
+///         Some(ext2::Ext2Error::Corrupt { what, .. }) => eprintln!("damaged image: {}", what),
+///         _ => eprintln!("{}", err),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub enum Ext2Error {
+    /// The on-disk structures are inconsistent, in the block given if known.
+    Corrupt {
+        what: String,
+        block: Option<u32>,
+    },
+    /// The filesystem uses a feature this crate does not implement.
+    Unsupported(String),
+    NotFound(PathBuf),
+    NotADirectory(PathBuf),
+    IsADirectory(PathBuf),
+    AlreadyExists(PathBuf),
+    DirectoryNotEmpty(PathBuf),
+    /// The file already has as many hard links as ext2 allows.
+    TooManyLinks(PathBuf),
+    /// Resolving the path followed too many symlinks.
+    SymlinkLoop(PathBuf),
+    /// The operation is not allowed on this file or handle.
+    PermissionDenied(String),
+    /// The arguments make no sense, whatever the state of the filesystem.
+    InvalidInput(String),
+    /// The filesystem may only be read.
+    ReadOnly,
+    /// There are no free blocks or inodes left.
+    NoSpace,
+    /// The underlying disk failed.
+    Io(io::Error),
+}
+
+impl Ext2Error {
+    /// The `Ext2Error` inside `err`, if it came from this crate.
+    pub fn of(err: &io::Error) -> Option<&Ext2Error> {
+        err.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+
+    /// The closest `io::ErrorKind`.
+    pub fn kind(&self) -> io::ErrorKind {
+        use self::Ext2Error::*;
+        match *self {
+            Corrupt { .. } => io::ErrorKind::InvalidData,
+            Unsupported(_) => io::ErrorKind::Unsupported,
+            NotFound(_) => io::ErrorKind::NotFound,
+            NotADirectory(_) => io::ErrorKind::NotADirectory,
+            IsADirectory(_) => io::ErrorKind::IsADirectory,
+            AlreadyExists(_) => io::ErrorKind::AlreadyExists,
+            DirectoryNotEmpty(_) => io::ErrorKind::DirectoryNotEmpty,
+            TooManyLinks(_) => io::ErrorKind::TooManyLinks,
+            SymlinkLoop(_) => io::ErrorKind::Other,
+            PermissionDenied(_) => io::ErrorKind::PermissionDenied,
+            InvalidInput(_) => io::ErrorKind::InvalidInput,
+            ReadOnly => io::ErrorKind::ReadOnlyFilesystem,
+            NoSpace => io::ErrorKind::StorageFull,
+            Io(ref err) => err.kind(),
+        }
+    }
+
+    pub(crate) fn corrupt<S: Into<String>>(what: S) -> Ext2Error {
+        Ext2Error::Corrupt {
+            what: what.into(),
+            block: None,
+        }
+    }
+
+    pub(crate) fn corrupt_block<S: Into<String>>(what: S, block: u32) -> Ext2Error {
+        Ext2Error::Corrupt {
+            what: what.into(),
+            block: Some(block),
+        }
+    }
+}
+
+impl fmt::Display for Ext2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Ext2Error::*;
+        match *self {
+            Corrupt {
+                ref what,
+                block: Some(block),
+            } => write!(f, "corrupt filesystem: {} (block {})", what, block),
+            Corrupt {
+                ref what,
+                block: None,
+            } => write!(f, "corrupt filesystem: {}", what),
+            Unsupported(ref feature) => write!(f, "unsupported filesystem feature: {}", feature),
+            NotFound(ref path) => write!(f, "{:?} not found", path),
+            NotADirectory(ref path) => write!(f, "{:?} is not a directory", path),
+            IsADirectory(ref path) => write!(f, "{:?} is a directory", path),
+            AlreadyExists(ref path) => write!(f, "{:?} already exists", path),
+            DirectoryNotEmpty(ref path) => write!(f, "{:?} is not empty", path),
+            TooManyLinks(ref path) => write!(f, "{:?} has too many links", path),
+            SymlinkLoop(ref path) => write!(f, "too many levels of symbolic links in {:?}", path),
+            PermissionDenied(ref msg) | InvalidInput(ref msg) => f.write_str(msg),
+            ReadOnly => f.write_str("filesystem is read-only"),
+            NoSpace => f.write_str("no space left on filesystem"),
+            Io(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Ext2Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Ext2Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Ext2Error {
+    /// Unwraps errors that came from this crate in the first place.
+    fn from(err: io::Error) -> Ext2Error {
+        if Ext2Error::of(&err).is_some() {
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<Ext2Error>().unwrap();
+        }
+        Ext2Error::Io(err)
+    }
+}
+
+impl From<Ext2Error> for io::Error {
+    fn from(err: Ext2Error) -> io::Error {
+        match err {
+            Ext2Error::Io(err) => err,
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! features.rs: Typed sets of the feature flags in the superblock.
+//!
+//! Each set displays as the space separated names `dumpe2fs` uses, with
+//! flags it does not know given by bit number.
+
+use std::fmt;
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// Features an implementation may ignore entirely
+    /// (`s_feature_compat`).
+    #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+    pub struct CompatFeatures: u32 {
+        /// Directories have blocks preallocated.
+        const DIR_PREALLOC = 0x0001;
+        /// AFS server inodes exist.
+        const IMAGIC_INODES = 0x0002;
+        /// There is an ext3 journal.
+        const HAS_JOURNAL = 0x0004;
+        /// Extended attributes are supported.
+        const EXT_ATTR = 0x0008;
+        /// Blocks are reserved for growing the descriptor table.
+        const RESIZE_INODE = 0x0010;
+        /// Directories may have hashed indexes.
+        const DIR_INDEX = 0x0020;
+        const LAZY_BG = 0x0040;
+        const SPARSE_SUPER2 = 0x0200;
+        const FAST_COMMIT = 0x0400;
+        const STABLE_INODES = 0x0800;
+        const ORPHAN_FILE = 0x1000;
+    }
+}
+
+bitflags! {
+    /// Features an implementation must understand to use the filesystem
+    /// at all (`s_feature_incompat`).
+    #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+    pub struct IncompatFeatures: u32 {
+        const COMPRESSION = 0x0001;
+        /// Directory entries record the file type.
+        const FILETYPE = 0x0002;
+        /// The journal needs replaying.
+        const NEEDS_RECOVERY = 0x0004;
+        /// The filesystem is an external journal.
+        const JOURNAL_DEV = 0x0008;
+        const META_BG = 0x0010;
+        /// Files map their blocks with extent trees.
+        const EXTENTS = 0x0040;
+        /// Block numbers are 64 bits wide.
+        const BIT64 = 0x0080;
+        /// Multiple mount protection.
+        const MMP = 0x0100;
+        /// Group metadata may live outside its group.
+        const FLEX_BG = 0x0200;
+        const EA_INODE = 0x0400;
+        const DIRDATA = 0x1000;
+        const CSUM_SEED = 0x2000;
+        const LARGEDIR = 0x4000;
+        const INLINE_DATA = 0x8000;
+        const ENCRYPT = 0x10000;
+        const CASEFOLD = 0x20000;
+    }
+}
+
+bitflags! {
+    /// Features an implementation must understand to modify the
+    /// filesystem, but may ignore when only reading it
+    /// (`s_feature_ro_compat`).
+    #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+    pub struct RoCompatFeatures: u32 {
+        /// Backup superblocks only in groups 0, 1 and powers of 3, 5 and 7.
+        const SPARSE_SUPER = 0x0001;
+        /// Files may be larger than 2GiB.
+        const LARGE_FILE = 0x0002;
+        const BTREE_DIR = 0x0004;
+        const HUGE_FILE = 0x0008;
+        /// Group descriptors carry checksums.
+        const GDT_CSUM = 0x0010;
+        const DIR_NLINK = 0x0020;
+        const EXTRA_ISIZE = 0x0040;
+        const QUOTA = 0x0100;
+        const BIGALLOC = 0x0200;
+        /// Metadata carries checksums.
+        const METADATA_CSUM = 0x0400;
+        const READONLY = 0x1000;
+        const PROJECT = 0x2000;
+        const VERITY = 0x8000;
+    }
+}
+
+/// The names `dumpe2fs` gives the flags.
+const COMPAT_NAMES: &[(u32, &str)] = &[
+    (0x0001, "dir_prealloc"),
+    (0x0002, "imagic_inodes"),
+    (0x0004, "has_journal"),
+    (0x0008, "ext_attr"),
+    (0x0010, "resize_inode"),
+    (0x0020, "dir_index"),
+    (0x0040, "lazy_bg"),
+    (0x0200, "sparse_super2"),
+    (0x0400, "fast_commit"),
+    (0x0800, "stable_inodes"),
+    (0x1000, "orphan_file"),
+];
+
+const INCOMPAT_NAMES: &[(u32, &str)] = &[
+    (0x0001, "compression"),
+    (0x0002, "filetype"),
+    (0x0004, "needs_recovery"),
+    (0x0008, "journal_dev"),
+    (0x0010, "meta_bg"),
+    (0x0040, "extent"),
+    (0x0080, "64bit"),
+    (0x0100, "mmp"),
+    (0x0200, "flex_bg"),
+    (0x0400, "ea_inode"),
+    (0x1000, "dirdata"),
+    (0x2000, "metadata_csum_seed"),
+    (0x4000, "large_dir"),
+    (0x8000, "inline_data"),
+    (0x10000, "encrypt"),
+    (0x20000, "casefold"),
+];
+
+const RO_COMPAT_NAMES: &[(u32, &str)] = &[
+    (0x0001, "sparse_super"),
+    (0x0002, "large_file"),
+    (0x0004, "btree_dir"),
+    (0x0008, "huge_file"),
+    (0x0010, "uninit_bg"),
+    (0x0020, "dir_nlink"),
+    (0x0040, "extra_isize"),
+    (0x0100, "quota"),
+    (0x0200, "bigalloc"),
+    (0x0400, "metadata_csum"),
+    (0x1000, "read-only"),
+    (0x2000, "project"),
+    (0x8000, "verity"),
+];
+
+impl fmt::Display for CompatFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_names(f, self.bits(), COMPAT_NAMES, "FEATURE_C")
+    }
+}
+
+impl fmt::Display for IncompatFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_names(f, self.bits(), INCOMPAT_NAMES, "FEATURE_I")
+    }
+}
+
+impl fmt::Display for RoCompatFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_names(f, self.bits(), RO_COMPAT_NAMES, "FEATURE_R")
+    }
+}
+
+fn write_names(
+    f: &mut fmt::Formatter,
+    bits: u32,
+    table: &[(u32, &str)],
+    prefix: &str,
+) -> fmt::Result {
+    let mut first = true;
+    for bit in 0..32 {
+        let flag = 1 << bit;
+        if bits & flag == 0 {
+            continue;
+        }
+        if !first {
+            f.write_str(" ")?;
+        }
+        first = false;
+        match table.iter().find(|&&(value, _)| value == flag) {
+            Some(&(_, name)) => f.write_str(name)?,
+            None => write!(f, "{}{}", prefix, bit)?,
+        }
+    }
+    Ok(())
+}
@@ -1,33 +1,232 @@
 use std::io;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use super::{Ext2, Inode, Superblock};
+use std::sync::Arc;
+use super::{Ext2, Ext2Error, Inode, Metadata, Superblock};
 use super::disk;
 
-pub struct Ext2Handle<'fs, T: disk::Disk + 'fs> {
-    fs: &'fs Ext2<T>,
+/// Options for opening a file, in the manner of `std::fs::OpenOptions`.
+///
+/// Opening with options needs a disk that can be written; on a read-only
+/// disk use `Ext2::open` instead.
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+    pub(crate) read: bool,
+    pub(crate) write: bool,
+    pub(crate) append: bool,
+    pub(crate) truncate: bool,
+    pub(crate) create: bool,
+    pub(crate) create_new: bool,
+    pub(crate) read_ahead: u32,
+}
+
+impl OpenOptions {
+    /// Create a blank set of options, with every option set to `false`.
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut OpenOptions {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut OpenOptions {
+        self.write = write;
+        self
+    }
+
+    /// Position every write at the current end of the file.  Implies
+    /// `write`.
+    pub fn append(&mut self, append: bool) -> &mut OpenOptions {
+        self.append = append;
+        self
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut OpenOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut OpenOptions {
+        self.create = create;
+        self
+    }
+
+    /// Create a new file, failing if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut OpenOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Read this many blocks beyond what each read asks for, to answer the
+    /// reads that follow from memory.  Defaults to 0.
+    ///
+    /// Blocks read ahead are dropped when the handle writes, but not when
+    /// the file is changed through another handle.
+    pub fn read_ahead(&mut self, blocks: u32) -> &mut OpenOptions {
+        self.read_ahead = blocks;
+        self
+    }
+
+    /// Open the file at `path` on `fs` with these options.
+    pub fn open<'fs, T, P>(&self, fs: &'fs Ext2<T>, path: P) -> io::Result<Ext2Handle<'fs, T>>
+    where
+        T: disk::WriteDisk + 'fs,
+        P: AsRef<Path>,
+    {
+        self.check()?;
+        fs.open_with(None, path, self)
+    }
+
+    /// Open the file at `path` on `fs` with these options, resolving a
+    /// relative path from the directory `dir` has open.
+    pub fn open_at<'fs, T, P>(
+        &self,
+        fs: &'fs Ext2<T>,
+        dir: &Ext2Handle<'_, T>,
+        path: P,
+    ) -> io::Result<Ext2Handle<'fs, T>>
+    where
+        T: disk::WriteDisk + 'fs,
+        P: AsRef<Path>,
+    {
+        self.check()?;
+        fs.open_with(Some(dir), path, self)
+    }
+
+    /// Open the file at `path` on `fs` with these options, returning a
+    /// handle that keeps `fs` alive instead of borrowing it.
+    pub fn open_owned<T, P>(&self, fs: &Arc<Ext2<T>>, path: P) -> io::Result<OwnedHandle<T>>
+    where
+        T: disk::WriteDisk + 'static,
+        P: AsRef<Path>,
+    {
+        self.check()?;
+        Ok(fs.open_with(None, path, self)?.into_owned(fs.clone()))
+    }
+
+    /// Reject combinations of options that make no sense.
+    fn check(&self) -> io::Result<()> {
+        let writable = self.write || self.append;
+        if !self.read && !writable {
+            return Err(Ext2Error::InvalidInput(
+                "file must be opened for reading or writing".to_owned(),
+            )
+            .into());
+        }
+        if !writable && (self.truncate || self.create || self.create_new) {
+            return Err(Ext2Error::InvalidInput(
+                "creating or truncating a file requires write access".to_owned(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// The filesystem a handle reads and writes through, either borrowed or
+/// shared.
+enum FsRef<'fs, T: disk::ReadDisk + 'fs> {
+    Borrowed(&'fs Ext2<T>),
+    Shared(Arc<Ext2<T>>),
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> Deref for FsRef<'fs, T> {
+    type Target = Ext2<T>;
+
+    fn deref(&self) -> &Ext2<T> {
+        match *self {
+            FsRef::Borrowed(fs) => fs,
+            FsRef::Shared(ref fs) => fs,
+        }
+    }
+}
+
+/// A handle that owns a share of its filesystem rather than borrowing it,
+/// as returned by `Ext2::open_owned`.  It can be stored, returned, or sent
+/// to another thread like any other owned value.
+pub type OwnedHandle<T> = Ext2Handle<'static, T>;
+
+pub struct Ext2Handle<'fs, T: disk::ReadDisk + 'fs> {
+    fs: FsRef<'fs, T>,
     superblock: Superblock,
     path: PathBuf,
+    inum: u32,
     inode: Inode,
     pos: u64,
+    readable: bool,
+    writable: bool,
+    append: bool,
+    read_ahead: u32,
+    /// Whole blocks of the file read ahead of `pos`, starting at byte
+    /// `buffer_start` of the file.
+    buffer: Vec<u8>,
+    buffer_start: u64,
 }
 
-impl<'fs, T: disk::Disk + 'fs> Ext2Handle<'fs, T> {
-    // HERE
+impl<'fs, T: disk::ReadDisk + 'fs> Ext2Handle<'fs, T> {
     pub fn new<P: AsRef<Path>>(
         fs: &'fs Ext2<T>,
         path: P,
         superblock: Superblock,
+        inum: u32,
         inode: Inode,
     ) -> Ext2Handle<'fs, T> {
         Ext2Handle {
-            fs,
+            fs: FsRef::Borrowed(fs),
             superblock,
             path: path.as_ref().to_owned(),
+            inum,
             inode,
             pos: 0,
+            readable: true,
+            writable: false,
+            append: false,
+            read_ahead: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
         }
     }
 
+    pub(crate) fn with_options(mut self, options: &OpenOptions) -> Ext2Handle<'fs, T> {
+        self.readable = options.read;
+        self.writable = options.write || options.append;
+        self.append = options.append;
+        self.read_ahead = options.read_ahead;
+        self
+    }
+
+    /// Swap the borrow of the filesystem for a share of `fs`, which must be
+    /// the same filesystem.
+    pub(crate) fn into_owned(self, fs: Arc<Ext2<T>>) -> OwnedHandle<T>
+    where
+        T: 'static,
+    {
+        Ext2Handle {
+            fs: FsRef::Shared(fs),
+            superblock: self.superblock,
+            path: self.path,
+            inum: self.inum,
+            inode: self.inode,
+            pos: self.pos,
+            readable: self.readable,
+            writable: self.writable,
+            append: self.append,
+            read_ahead: self.read_ahead,
+            buffer: self.buffer,
+            buffer_start: self.buffer_start,
+        }
+    }
+
+    pub(crate) fn inum(&self) -> u32 {
+        self.inum
+    }
+
+    pub(crate) fn inode(&self) -> &Inode {
+        &self.inode
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
@@ -35,9 +234,173 @@ impl<'fs, T: disk::Disk + 'fs> Ext2Handle<'fs, T> {
     pub fn size(&self) -> u64 {
         self.inode.size()
     }
+
+    /// Change how many blocks reads read ahead, as set by
+    /// `OpenOptions::read_ahead`.
+    pub fn set_read_ahead(&mut self, blocks: u32) {
+        self.read_ahead = blocks;
+    }
+
+    /// Read the file's metadata afresh from disk.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        let inode = self.fs.get_inode(self.inum, &self.superblock)?;
+        Ok(Metadata::new(self.inum, inode, &self.superblock))
+    }
+
+    /// Read from byte `offset` of the file into `buf`, without moving the
+    /// handle's position, in the manner of `FileExt::read_at`.  Returns the
+    /// number of bytes read, stopping early only at the end of the file.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        if !self.readable {
+            return Err(
+                Ext2Error::PermissionDenied("file not opened for reading".to_owned()).into(),
+            );
+        }
+        if offset >= self.inode.size() {
+            return Ok(0);
+        }
+        let bs = self.superblock.block_size() as u64;
+        let want = (self.inode.size() - offset).min(buf.len() as u64) as usize;
+        let mut block = Vec::new();
+        let mut done = 0;
+        while done < want {
+            let pos = offset + done as u64;
+            let mut read = self.read_buffered(pos, &mut buf[done..want]);
+            if read == 0 {
+                let whole = (want - done) / bs as usize;
+                if pos.is_multiple_of(bs) && whole > 0 {
+                    let blocks = self.fs.read_inode_data_blocks(
+                        &self.inode,
+                        &mut buf[done..],
+                        (pos / bs) as u32,
+                        whole as u32,
+                        &self.superblock,
+                    )?;
+                    read = blocks as usize * bs as usize;
+                } else {
+                    // Read the block through a scratch buffer, since the
+                    // read-ahead buffer can't be filled from `&self`.
+                    block.resize(bs as usize, 0);
+                    let blocks = self.fs.read_inode_data_blocks(
+                        &self.inode,
+                        &mut block,
+                        (pos / bs) as u32,
+                        1,
+                        &self.superblock,
+                    )?;
+                    if blocks > 0 {
+                        let start = (pos % bs) as usize;
+                        read = (bs as usize - start).min(want - done);
+                        buf[done..done + read].copy_from_slice(&block[start..start + read]);
+                    }
+                }
+            }
+            if read == 0 {
+                break;
+            }
+            done += read;
+        }
+        Ok(done)
+    }
+
+    /// Fill `buf` from byte `offset` of the file, without moving the
+    /// handle's position, in the manner of `FileExt::read_exact_at`.
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset)? {
+                0 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ))
+                }
+                n => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The offset of the first data in the file at or after `offset`, in
+    /// the manner of `lseek` with `SEEK_DATA`, or `None` if there is only
+    /// hole from `offset` to the end of the file.
+    ///
+    /// Holes are found a block at a time, so the offset returned may be
+    /// that of a block which holds only zeros.
+    pub fn next_data(&self, offset: u64) -> io::Result<Option<u64>> {
+        if offset >= self.inode.size() {
+            return Ok(None);
+        }
+        let bs = self.superblock.block_size() as u64;
+        let found = self.fs.find_block(&self.inode, offset / bs, true, &self.superblock)?;
+        Ok(found.map(|block| offset.max(block * bs)))
+    }
+
+    /// The offset of the first hole in the file at or after `offset`, in
+    /// the manner of `lseek` with `SEEK_HOLE`, or `None` if `offset` is at
+    /// or past the end of the file.  The end of the file counts as a hole.
+    ///
+    /// Together with `next_data`, this lets a sparse file be copied
+    /// without reading its holes:
+    ///
+    /// ```no_run
+    /// # fn copy<T: ext2::ReadDisk>(f: &ext2::handle::Ext2Handle<'_, T>) -> std::io::Result<()> {
+    /// let mut pos = 0;
+    /// while let Some(start) = f.next_data(pos)? {
+    ///     let end = f.next_hole(start)?.unwrap();
+    ///     let mut data = vec![0; (end - start) as usize];
+    ///     f.read_exact_at(&mut data, start)?;
+    ///     // Write `data` at `start` of the copy.
+    ///     pos = end;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn next_hole(&self, offset: u64) -> io::Result<Option<u64>> {
+        let size = self.inode.size();
+        if offset >= size {
+            return Ok(None);
+        }
+        let bs = self.superblock.block_size() as u64;
+        let found = self.fs.find_block(&self.inode, offset / bs, false, &self.superblock)?;
+        Ok(Some(found.map_or(size, |block| offset.max(block * bs).min(size))))
+    }
+
+    /// Copy what the read-ahead buffer holds at `pos` into `buf`.
+    fn read_buffered(&self, pos: u64, buf: &mut [u8]) -> usize {
+        let end = self.buffer_start + self.buffer.len() as u64;
+        if pos < self.buffer_start || pos >= end {
+            return 0;
+        }
+        let start = (pos - self.buffer_start) as usize;
+        let len = buf.len().min(self.buffer.len() - start);
+        buf[..len].copy_from_slice(&self.buffer[start..start + len]);
+        len
+    }
+
+    /// Read `count` blocks of the file from block `first` into the buffer,
+    /// or as many as the file has.
+    fn fill_buffer(&mut self, first: u64, count: u64) -> io::Result<()> {
+        let bs = self.superblock.block_size() as u64;
+        let in_file = self.inode.size().div_ceil(bs).saturating_sub(first);
+        let count = count.min(in_file) as u32;
+        self.buffer.resize(count as usize * bs as usize, 0);
+        let read = self.fs.read_inode_data_blocks(
+            &self.inode,
+            &mut self.buffer,
+            first as u32,
+            count,
+            &self.superblock,
+        )?;
+        self.buffer.truncate(read as usize * bs as usize);
+        self.buffer_start = first * bs;
+        Ok(())
+    }
 }
 
-impl<'fs, T: disk::Disk + 'fs> io::Seek for Ext2Handle<'fs, T> {
+impl<'fs, T: disk::ReadDisk + 'fs> io::Seek for Ext2Handle<'fs, T> {
     fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
         use io::SeekFrom::*;
         let (base, offset) = match pos {
@@ -58,42 +421,93 @@ impl<'fs, T: disk::Disk + 'fs> io::Seek for Ext2Handle<'fs, T> {
                 self.pos = n;
                 Ok(n)
             }
-            None => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "attempted to seek to negative or overflowing position",
-            )),
+            None => Err(Ext2Error::InvalidInput(
+                "attempted to seek to negative or overflowing position".to_owned(),
+            )
+            .into()),
         }
     }
 }
 
-impl<'fs, T: disk::Disk + 'fs> io::Read for Ext2Handle<'fs, T> {
+impl<'fs, T: disk::ReadDisk + 'fs> io::Read for Ext2Handle<'fs, T> {
+    /// Read as much of `buf` as the file allows, stopping early only at the
+    /// end of the file.  Holes in sparse files read as zeros.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.readable {
+            return Err(
+                Ext2Error::PermissionDenied("file not opened for reading".to_owned()).into(),
+            );
+        }
+        if self.pos >= self.inode.size() {
+            return Ok(0);
+        }
         let bs = self.superblock.block_size() as u64;
-        let blocknum = (self.pos / bs) as u32;
-        let remaining = self.inode.size() - self.pos;
-        let offset = (self.pos % bs) as usize;
-        let read = if self.pos % bs == 0 && buf.len() >= bs as usize && remaining >= bs {
-            // Read an entire block cleanly into the provided buffer.
-            self.fs
-                .read_inode_data_block(&self.inode, buf, blocknum, &self.superblock)?
-        } else {
-            // Read a partial block into the provided buffer, using an internal
-            // buffer to read a whole block off the disk, with a length no
-            // greater than the length of the provided buffer or the number of
-            // bytes remaining in the file.
-            let mut innerbuf = vec![0; bs as usize];
-            let len = self.fs.read_inode_data_block(
-                &self.inode,
-                &mut innerbuf,
-                blocknum,
-                &self.superblock,
-            )?;
-            let len = len.min(buf.len());
-            let len = len.min(remaining as usize);
-            buf[..len].copy_from_slice(&innerbuf[offset..len + offset]);
-            len
-        };
-        self.pos += read as u64;
-        Ok(read)
+        let want = (self.inode.size() - self.pos).min(buf.len() as u64) as usize;
+        let mut done = 0;
+        while done < want {
+            let pos = self.pos + done as u64;
+            let mut read = self.read_buffered(pos, &mut buf[done..want]);
+            if read == 0 {
+                let whole = (want - done) / bs as usize;
+                if pos.is_multiple_of(bs) && whole > 0 {
+                    // Read whole blocks straight into the provided buffer.
+                    let blocks = self.fs.read_inode_data_blocks(
+                        &self.inode,
+                        &mut buf[done..],
+                        (pos / bs) as u32,
+                        whole as u32,
+                        &self.superblock,
+                    )?;
+                    read = blocks as usize * bs as usize;
+                    let next = pos + read as u64;
+                    if self.read_ahead > 0 && blocks as usize == whole && done + read == want {
+                        self.fill_buffer(next / bs, self.read_ahead as u64)?;
+                    }
+                } else {
+                    // Buffer only the partial block at `pos`, leaving any
+                    // whole blocks after it to be read straight into `buf`,
+                    // and read ahead only if the request ends in it.
+                    let last = (pos + (want - done) as u64 - 1) / bs;
+                    let ahead = if last == pos / bs { self.read_ahead as u64 } else { 0 };
+                    self.fill_buffer(pos / bs, 1 + ahead)?;
+                    read = self.read_buffered(pos, &mut buf[done..want]);
+                }
+            }
+            if read == 0 {
+                break;
+            }
+            done += read;
+        }
+        self.pos += done as u64;
+        Ok(done)
+    }
+}
+
+impl<'fs, T: disk::WriteDisk + 'fs> io::Write for Ext2Handle<'fs, T> {
+    /// Write up to the end of the current block, allocating blocks as the
+    /// file grows.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.writable {
+            return Err(
+                Ext2Error::PermissionDenied("file not opened for writing".to_owned()).into(),
+            );
+        }
+        if self.append {
+            self.pos = self.inode.size();
+        }
+        self.buffer.clear();
+        let written = self.fs.write_inode_data(
+            self.inum,
+            &mut self.inode,
+            buf,
+            self.pos,
+            &self.superblock,
+        )?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.fs.sync()
     }
 }
@@ -2,7 +2,6 @@
 //!
 //! * Change io::Result<Option<T>> to io::Result<T> using
 //!   io::ErrorKind::NotFound in place of Ok(None)
-//! * Implement write
 
 extern crate byteorder;
 #[macro_use]
@@ -13,32 +12,204 @@ use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io;
 use std::os::unix::ffi::OsStrExt;
-use std::path::{Component, Path};
-use std::sync::Mutex;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use byteorder::{ByteOrder, LE};
 
 mod disk;
 mod array;
+mod cache;
+mod error;
+mod features;
+mod dir;
+mod metadata;
+mod read_dir;
+mod symlink;
+mod walk;
+pub mod alloc;
 pub mod handle;
+pub mod mkfs;
 
-pub use disk::Disk;
-pub struct Ext2<T: disk::Disk>(Mutex<T>);
+pub use cache::{BlockCache, CacheStats};
+pub use disk::{ReadAt, ReadAtDisk, ReadDisk, ReadOnlyDisk, SectorDisk, WriteDisk};
+pub use error::Ext2Error;
+pub use features::{CompatFeatures, IncompatFeatures, RoCompatFeatures};
+pub use handle::{OpenOptions, OwnedHandle};
+pub use metadata::{Metadata, Permissions};
+pub use mkfs::Mkfs;
+pub use read_dir::{Entry, ReadDir};
+pub use walk::{Walk, WalkEntry};
+
+/// Superblock magic number.
+const EXT2_SUPER_MAGIC: u16 = 0xEF53;
+/// Block sizes above 64KiB are not supported by any implementation.
+const EXT2_MAX_BLOCK_LOG_SIZE: u32 = 6;
+/// How a record spanning a whole 64KiB block is stored in the 16-bit
+/// `rec_len`, as in Linux; older tools wrote 0 instead.
+const EXT2_MAX_REC_LEN: u16 = 0xffff;
+/// Symlinks followed in a row before giving up, as in Linux.
+const MAX_SYMLINKS: usize = 40;
+/// Directory has a hashed (htree) index.
+const EXT2_INDEX_FL: u32 = 0x1000;
+/// The incompatible features this crate implements.
+const EXT2_FEATURE_INCOMPAT_SUPP: IncompatFeatures = IncompatFeatures::FILETYPE;
+/// The read-only compatible features this crate implements.
+const EXT2_FEATURE_RO_COMPAT_SUPP: RoCompatFeatures =
+    RoCompatFeatures::SPARSE_SUPER.union(RoCompatFeatures::LARGE_FILE);
+
+pub struct Ext2<T: disk::ReadDisk> {
+    /// Held for reading only by reads that can go through
+    /// `ReadDisk::read_sectors_at`; everything else needs it for writing.
+    disk: RwLock<T>,
+    /// Serialises read-modify-write cycles on the bitmaps, the block group
+    /// descriptors and the superblock.
+    meta: Mutex<()>,
+    /// The superblock and descriptor table, or `None` until `Mkfs` has
+    /// written them.
+    cache: RwLock<Option<Cache>>,
+    /// Set when the filesystem uses features we can read but not update.
+    read_only: bool,
+}
+
+/// The metadata needed by nearly every operation, kept in memory so that
+/// path lookups need not read it again.  Writes go straight through to disk
+/// as well as updating the copy here.
+struct Cache {
+    /// The raw primary superblock, so that fields we do not parse survive
+    /// being written back.
+    raw_superblock: [u8; 1024],
+    superblock: Superblock,
+    /// The raw block group descriptor table, in whole blocks.
+    descriptors: Vec<u8>,
+}
 
 /// Ext2 Filesystem
-impl<T: disk::Disk> Ext2<T> {
+impl<T: disk::ReadDisk> Ext2<T> {
+    /// Mount the filesystem on `disk`.
+    ///
+    /// Fails if `disk` does not hold a valid ext2 superblock, or if the
+    /// filesystem uses incompatible features this crate does not implement;
+    /// the error names them.  Filesystems with unknown read-only compatible
+    /// features are mounted read-only.
     pub fn new(disk: T) -> io::Result<Ext2<T>> {
-        Ok(Ext2(Mutex::new(disk)))
+        let mut fs = Ext2::unchecked(disk);
+        fs.refresh()?;
+        let sb = fs.superblock()?;
+        let incompat = sb.incompat_features() - EXT2_FEATURE_INCOMPAT_SUPP;
+        if !incompat.is_empty() {
+            return Err(Ext2Error::Unsupported(incompat.to_string()).into());
+        }
+        fs.read_only = !(sb.ro_compat_features() - EXT2_FEATURE_RO_COMPAT_SUPP).is_empty();
+        Ok(fs)
+    }
+
+    /// Wrap `disk` without looking at it, for `Mkfs` to fill in.
+    pub(crate) fn unchecked(disk: T) -> Ext2<T> {
+        Ext2 {
+            disk: RwLock::new(disk),
+            meta: Mutex::new(()),
+            cache: RwLock::new(None),
+            read_only: false,
+        }
+    }
+
+    /// Read the superblock and block group descriptors from disk again,
+    /// discarding the copies kept since the filesystem was mounted.
+    ///
+    /// This is only needed if something other than this `Ext2` has
+    /// changed the disk; changes made through it are written straight
+    /// through and kept in step.
+    pub fn refresh(&self) -> io::Result<()> {
+        let raw_superblock = self.read_superblock_bytes()?;
+        let superblock = Superblock::new(&raw_superblock)?;
+        superblock.check()?;
+        let bs = superblock.block_size() as usize;
+        let table_len = superblock.block_group_count() as usize * 32;
+        let first = self.first_descriptor_block(&superblock);
+        // Read a block at a time, so that a corrupt group count fails at
+        // the end of the disk rather than on a huge allocation.
+        let mut descriptors = Vec::new();
+        let mut block = first;
+        while descriptors.len() < table_len {
+            let start = descriptors.len();
+            descriptors.resize(start + bs, 0);
+            self.read_block(block, &mut descriptors[start..], &superblock)?;
+            block += 1;
+        }
+        *self.cache.write().expect("Got a poisoned lock.  Cannot recover") = Some(Cache {
+            raw_superblock,
+            superblock,
+            descriptors,
+        });
+        Ok(())
+    }
+
+    /// Whether the filesystem was mounted read-only, because it uses
+    /// read-only compatible features this crate cannot keep up to date.
+    /// Every attempt to modify it fails with `Ext2Error::ReadOnly`.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The read-only compatible features this crate does not implement,
+    /// which forced the filesystem to be mounted read-only.
+    pub fn read_only_features(&self) -> io::Result<RoCompatFeatures> {
+        let sb = self.superblock()?;
+        Ok(sb.ro_compat_features() - EXT2_FEATURE_RO_COMPAT_SUPP)
+    }
+
+    /// Consume the filesystem, returning the underlying disk.
+    pub fn into_inner(self) -> T {
+        self.disk
+            .into_inner()
+            .expect("Got a poisoned lock.  Cannot recover")
+    }
+
+    /// Run `f` on the underlying disk, for instance to read the statistics
+    /// of a `BlockCache`.
+    pub fn with_disk<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.disk.write().expect("Got a poisoned lock.  Cannot recover"))
     }
 
     pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<handle::Ext2Handle<'_, T>> {
+        self.open_read(None, path.as_ref())
+    }
+
+    /// Open the file at `path`, resolved from the directory that `dir` has
+    /// open rather than from the root.
+    pub fn open_at<P: AsRef<Path>>(
+        &self,
+        dir: &handle::Ext2Handle<'_, T>,
+        path: P,
+    ) -> io::Result<handle::Ext2Handle<'_, T>> {
+        self.open_read(Some(dir), path.as_ref())
+    }
+
+    /// Open the file at `path` for reading, returning a handle that holds
+    /// a share of the filesystem rather than a borrow, so that it can be
+    /// kept past the borrow or sent to another thread.
+    pub fn open_owned<P: AsRef<Path>>(self: &Arc<Self>, path: P) -> io::Result<OwnedHandle<T>>
+    where
+        T: 'static,
+    {
+        Ok(self.open_read(None, path.as_ref())?.into_owned(self.clone()))
+    }
+
+    /// Open `path` for reading only, resolving a relative path from `dir`
+    /// if given, or from the root if not.
+    fn open_read(
+        &self,
+        dir: Option<&handle::Ext2Handle<'_, T>>,
+        path: &Path,
+    ) -> io::Result<handle::Ext2Handle<'_, T>> {
         let superblock = self.superblock()?;
-        if let Some(inode) = self.get_inode_from_abspath(&path, &superblock)? {
-            Ok(handle::Ext2Handle::new(self, &path, superblock, inode))
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("{:?} not found", path.as_ref()),
-            ))
+        match self.lookup_from(open_base(dir)?, path, true, &superblock)? {
+            Some((inum, inode)) => {
+                let path = open_path(dir, path);
+                Ok(handle::Ext2Handle::new(self, path, superblock, inum, inode))
+            }
+            None => Err(Ext2Error::NotFound(path.to_owned()).into()),
         }
     }
 
@@ -46,34 +217,79 @@ impl<T: disk::Disk> Ext2<T> {
         self.superblock().map(|sb| sb.block_size())
     }
 
+    /// Get an allocator for the blocks and inodes of this filesystem.
+    pub fn allocator(&self) -> io::Result<alloc::Allocator<'_, T>> {
+        Ok(alloc::Allocator::new(self, &self.superblock()?))
+    }
+
     fn read_block(&self, blocknum: u32, buf: &mut [u8], sb: &Superblock) -> io::Result<()> {
+        self.read_blocks(blocknum, 1, buf, sb)
+    }
+
+    /// Read `count` physically contiguous blocks starting at `blocknum`,
+    /// holding the disk for the whole run.
+    fn read_blocks(
+        &self,
+        blocknum: u32,
+        count: u32,
+        buf: &mut [u8],
+        sb: &Superblock,
+    ) -> io::Result<()> {
         let block_size = sb.block_size();
-        if buf.len() < block_size as usize {
-            panic!("Must provide a buffer of size {}", block_size);
-        }
-        let sectors_per_block = block_size / 512;
-        let start_sector = sectors_per_block * blocknum;
-        for i in 0..sectors_per_block {
-            let start = (i * 512) as usize;
-            let end = start + 512;
-            self.0
-                .lock()
-                .expect("Got a poisoned mutex.  Cannot recover")
-                .read_sector((start_sector + i) as u64, &mut buf[start..end])?;
+        let len = block_size as usize * count as usize;
+        if buf.len() < len {
+            return Err(Ext2Error::InvalidInput(format!(
+                "buffer of {} bytes is smaller than {} blocks",
+                buf.len(),
+                count
+            )).into());
         }
-        Ok(())
+        self.read_bytes(block_size as u64 * blocknum as u64, &mut buf[..len])
     }
 
-    fn superblock(&self) -> io::Result<Superblock> {
-        let mut block = [0; 1024];
+    /// Read `buf.len()` bytes from `offset` on the disk, whatever the size
+    /// of its sectors.
+    fn read_bytes(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
         {
-            let mut disk = self.0
-                .lock()
-                .expect("Got a poisoned mutex.  Cannot recover");
-            disk.read_sector(2, &mut block[..512])?;
-            disk.read_sector(3, &mut block[512..])?;
+            let disk = self.disk
+                .read()
+                .expect("Got a poisoned lock.  Cannot recover");
+            if disk.can_read_at() {
+                let ss = disk.sector_size();
+                return read_unaligned(ss, offset, buf, |start, sectors| {
+                    disk.read_sectors_at(start, sectors)
+                });
+            }
         }
-        Superblock::new(&block)
+        let mut disk = self.disk
+            .write()
+            .expect("Got a poisoned lock.  Cannot recover");
+        let ss = disk.sector_size();
+        read_unaligned(ss, offset, buf, |start, sectors| disk.read_sectors(start, sectors))
+    }
+
+    fn read_superblock_bytes(&self) -> io::Result<[u8; 1024]> {
+        let mut block = [0; 1024];
+        self.read_bytes(1024, &mut block)?;
+        Ok(block)
+    }
+
+    /// Run `f` on the cached metadata.
+    fn with_cache<F, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce(&Cache) -> R,
+    {
+        let cache = self.cache
+            .read()
+            .expect("Got a poisoned lock.  Cannot recover");
+        match *cache {
+            Some(ref cache) => Ok(f(cache)),
+            None => Err(not_formatted()),
+        }
+    }
+
+    fn superblock(&self) -> io::Result<Superblock> {
+        self.with_cache(|cache| cache.superblock.clone())
     }
 
     fn first_descriptor_block(&self, sb: &Superblock) -> u32 {
@@ -88,145 +304,236 @@ impl<T: disk::Disk> Ext2<T> {
         &self,
         groupnum: u32,
         sb: &Superblock,
-    ) -> io::Result<Option<BlockGroupDescriptor>> {
-        if groupnum > sb.block_group_count() {
-            Ok(None)
-        } else {
-            let bs = sb.block_size();
-            let descriptor_block = (groupnum * 32) / bs + self.first_descriptor_block(sb);
-            let offset = ((groupnum * 32) % bs) as usize;
-            let mut buf = vec![0; bs as usize];
-            self.read_block(descriptor_block, &mut buf, sb)?;
-            Ok(Some(BlockGroupDescriptor::new(&buf[offset..offset + 32])?))
+    ) -> io::Result<BlockGroupDescriptor> {
+        if groupnum >= sb.block_group_count() {
+            let what = format!("block group {} does not exist", groupnum);
+            return Err(Ext2Error::corrupt(what).into());
         }
+        let offset = groupnum as usize * 32;
+        self.with_cache(|cache| match cache.descriptors.get(offset..offset + 32) {
+            Some(data) => BlockGroupDescriptor::new(data),
+            None => Err(not_formatted()),
+        })?
     }
 
-    fn get_inode(&self, iptr: u32, sb: &Superblock) -> io::Result<Option<Inode>> {
+    fn get_inode(&self, iptr: u32, sb: &Superblock) -> io::Result<Inode> {
+        if iptr == 0 || iptr > sb.s_inodes_count {
+            return Err(Ext2Error::corrupt(format!("inode {} does not exist", iptr)).into());
+        }
         let (igroup, ioffset) = sb.locate_inode(iptr);
-        let descriptor = self.get_block_group_descriptor(igroup, sb)?.unwrap();
+        let descriptor = self.get_block_group_descriptor(igroup, sb)?;
         let iblock = descriptor.bg_inode_table + (ioffset * sb.inode_size()) / sb.block_size();
         let iblock_offset = ((ioffset * sb.inode_size()) % sb.block_size()) as usize;
         let mut buf = vec![0; sb.block_size() as usize];
         self.read_block(iblock, &mut buf[..], sb)?;
-        Ok(Some(Inode::new(
-            &buf[iblock_offset..iblock_offset + sb.inode_size() as usize],
-        )?))
+        Inode::new(&buf[iblock_offset..iblock_offset + sb.inode_size() as usize])
     }
 
     fn get_root_directory(&self, sb: &Superblock) -> io::Result<Inode> {
-        self.get_inode(2, sb).map(|optinode| optinode.unwrap())
+        self.get_inode(2, sb)
+    }
+
+    /// Find the inode at `path`, following any symlinks.  Relative paths
+    /// are resolved from the root directory.
+    fn lookup<P: AsRef<Path>>(&self, path: P, sb: &Superblock) -> io::Result<Option<(u32, Inode)>> {
+        self.lookup_from(None, path.as_ref(), true, sb)
     }
 
-    fn get_inode_from_abspath<P: AsRef<Path>>(
+    /// Find the inode at `path`, following symlinks in the directories
+    /// leading to it but not a symlink at the end.
+    fn lookup_link<P: AsRef<Path>>(
         &self,
         path: P,
         sb: &Superblock,
-    ) -> io::Result<Option<Inode>> {
+    ) -> io::Result<Option<(u32, Inode)>> {
+        self.lookup_from(None, path.as_ref(), false, sb)
+    }
+
+    /// Find the inode at `path`, resolving a relative path from directory
+    /// `base`, or from the root if there is none.
+    fn lookup_from(
+        &self,
+        base: Option<(u32, &Inode)>,
+        path: &Path,
+        follow: bool,
+        sb: &Superblock,
+    ) -> io::Result<Option<(u32, Inode)>> {
+        Ok(self
+            .resolve(base, path, follow, sb)?
+            .and_then(|mut steps| steps.pop())
+            .map(|step| (step.inum, step.inode)))
+    }
+
+    /// The absolute path of `path` with every symlink, `.` and `..`
+    /// resolved away.  Relative paths are resolved from the root directory.
+    pub fn canonicalize<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
         let path = path.as_ref();
-        assert!(
-            path.is_absolute(),
-            "This library only supports absolute paths."
-        );
-        let mut inode = Inode::default();
-        for component in path.components() {
-            match component {
-                Component::RootDir => inode = self.get_root_directory(sb)?,
-                Component::Prefix(_) => {
-                    panic!("Prefix found in path.  I don't speak Windows");
+        let sb = self.superblock()?;
+        let steps = self
+            .resolve(None, path, true, &sb)?
+            .ok_or_else(|| Ext2Error::NotFound(path.to_owned()))?;
+        let mut canonical = PathBuf::from("/");
+        canonical.extend(steps.iter().skip(1).map(|step| &step.name));
+        Ok(canonical)
+    }
+
+    /// Walk `path` a component at a time, from `base` or the root, returning
+    /// the directories passed through on the way and the inode reached.
+    ///
+    /// A symlink is followed by splicing the components of its target into
+    /// the path, starting again from the root if the target is absolute.
+    /// The final component is only followed if `follow` is set.  `..` goes
+    /// back to the previous directory, or to the one named by the `..` entry
+    /// when backing out of `base`.
+    fn resolve(
+        &self,
+        base: Option<(u32, &Inode)>,
+        path: &Path,
+        follow: bool,
+        sb: &Superblock,
+    ) -> io::Result<Option<Vec<Step>>> {
+        let root = Step {
+            name: OsString::new(),
+            inum: 2,
+            inode: self.get_root_directory(sb)?,
+        };
+        let mut steps = match base {
+            Some((inum, inode)) if !path.has_root() => vec![Step {
+                name: OsString::new(),
+                inum,
+                inode: inode.clone(),
+            }],
+            _ => vec![root.clone()],
+        };
+        // Components still to resolve, the next one last.
+        let mut pending = Vec::new();
+        push_components(&mut pending, path)?;
+        let mut links = 0;
+        while let Some(component) = pending.pop() {
+            let current = steps.last().unwrap();
+            if current.inode.file_type() != FileType::Directory {
+                return Err(Ext2Error::NotADirectory(path.to_owned()).into());
+            }
+            let name = match component {
+                Pending::ParentDir if steps.len() > 1 => {
+                    steps.pop();
+                    continue;
                 }
-                component => {
-                    inode = match self.get_inode_in_dir(&inode, component.as_os_str(), sb)? {
-                        Some(inode) => inode,
-                        None => return Ok(None),
+                Pending::ParentDir if current.inum == 2 => continue,
+                Pending::ParentDir => {
+                    // Backing out of the base directory, into parts of the
+                    // tree we have not seen.
+                    let inum = self
+                        .get_inum_in_dir(&current.inode, OsStr::new(".."), sb)?
+                        .ok_or_else(|| Ext2Error::corrupt("directory has no \"..\" entry"))?;
+                    let inode = self.get_inode(inum, sb)?;
+                    steps[0] = Step {
+                        name: OsString::new(),
+                        inum,
+                        inode,
                     };
+                    continue;
                 }
+                Pending::Normal(name) => name,
+            };
+            let inum = match self.get_inum_in_dir(&current.inode, &name, sb)? {
+                Some(inum) => inum,
+                None => return Ok(None),
+            };
+            let inode = self.get_inode(inum, sb)?;
+            if inode.file_type() == FileType::Unknown {
+                return Err(Ext2Error::corrupt(format!(
+                    "inode {} has invalid mode 0o{:o}",
+                    inum, inode.i_mode
+                )).into());
             }
+            if inode.file_type() == FileType::SymLink && (follow || !pending.is_empty()) {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(Ext2Error::SymlinkLoop(path.to_owned()).into());
+                }
+                let target = PathBuf::from(self.read_link_target(&inode, sb)?);
+                if target.has_root() {
+                    steps = vec![root.clone()];
+                }
+                push_components(&mut pending, &target)?;
+                continue;
+            }
+            steps.push(Step { name, inum, inode });
         }
-        Ok(Some(inode))
+        Ok(Some(steps))
     }
 
-    fn get_inode_in_dir(
+    fn get_inum_in_dir(
         &self,
         inode: &Inode,
         filename: &OsStr,
         sb: &Superblock,
-    ) -> io::Result<Option<Inode>> {
-        if let Some(entries) = self.read_dir(inode, sb)? {
+    ) -> io::Result<Option<u32>> {
+        if let Some(entries) = self.dir_entries(inode, sb)? {
             for entry in entries {
-                if entry.name == filename {
-                    return self.get_inode(entry.inode, sb);
+                if entry.inode != 0 && entry.name == filename {
+                    return Ok(Some(entry.inode));
                 }
             }
         }
         Ok(None)
     }
 
-    fn find_ptr(&self, nextptr: u32, offset: u32, level: u32, sb: &Superblock) -> io::Result<u32> {
-        if level == 0 {
-            Ok(nextptr)
-        } else {
-            let mut buf = vec![0; sb.block_size() as usize];
-            if nextptr == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    "unexpectedly missing block",
-                ));
-            }
-            self.read_block(nextptr, &mut buf, sb)?;
-            let ptrs_per_block = sb.block_size() / 4;
-            let ptrs_per_bucket = ptrs_per_block.pow(level);
-            let skipped_buckets = offset / ptrs_per_bucket;
-            let next_offset = offset - ptrs_per_bucket * skipped_buckets;
-            let nextptr =
-                LE::read_u32(&buf[next_offset as usize * 4..(next_offset as usize + 1) * 4]);
-            self.find_ptr(nextptr, next_offset, level - 1, sb)
+    /// Follow the pointer to block `offset` of the tree of `level` levels of
+    /// indirection at `nextptr`.  Returns 0 if the block is a hole.
+    fn find_ptr(&self, nextptr: u32, offset: u64, level: u32, sb: &Superblock) -> io::Result<u32> {
+        if level == 0 || nextptr == 0 {
+            // A zero pointer is a hole spanning its whole tree.
+            return Ok(nextptr);
         }
+        let mut buf = vec![0; sb.block_size() as usize];
+        self.read_block(nextptr, &mut buf, sb)?;
+        let ptrs_per_bucket = (sb.block_size() as u64 / 4).pow(level - 1);
+        let index = (offset / ptrs_per_bucket) as usize;
+        let nextptr = LE::read_u32(&buf[index * 4..(index + 1) * 4]);
+        self.find_ptr(nextptr, offset % ptrs_per_bucket, level - 1, sb)
     }
 
     fn get_block_ptr(&self, inode: &Inode, idx: u32, sb: &Superblock) -> io::Result<u32> {
-        let blocksize = sb.block_size();
-        let inodes_per_block = blocksize / sb.inode_size();
+        let idx = idx as u64;
+        let ptrs_per_block = sb.block_size() as u64 / 4;
         let direct_limit = 12;
-        let single_limit = direct_limit + inodes_per_block;
-        let double_limit = single_limit + inodes_per_block * inodes_per_block;
-        let triple_limit = double_limit + inodes_per_block * inodes_per_block * inodes_per_block;
+        let single_limit = direct_limit + ptrs_per_block;
+        let double_limit = single_limit + ptrs_per_block * ptrs_per_block;
+        let triple_limit = double_limit + ptrs_per_block * ptrs_per_block * ptrs_per_block;
 
         let node = if idx < direct_limit {
-            let level = 0;
-            self.find_ptr(inode.i_block.0[idx as usize], 0, level, sb)?
+            inode.i_block.0[idx as usize]
         } else if idx < single_limit {
-            let level = 1;
-            self.find_ptr(inode.i_block.1, idx - direct_limit, level, sb)?
+            self.find_ptr(inode.i_block.1, idx - direct_limit, 1, sb)?
         } else if idx < double_limit {
-            let level = 2;
-            self.find_ptr(inode.i_block.2, idx - single_limit, level, sb)?
+            self.find_ptr(inode.i_block.2, idx - single_limit, 2, sb)?
         } else if idx < triple_limit {
-            let level = 3;
-            self.find_ptr(inode.i_block.3, idx - double_limit, level, sb)?
+            self.find_ptr(inode.i_block.3, idx - double_limit, 3, sb)?
         } else {
             0
         };
-        match node {
-            //0 => Err(io::Error::new(io::ErrorKind::NotFound, "End of the line")),
-            x => Ok(x),
-        }
+        Ok(node)
     }
 
-    /// TODO: Handle multiple block directories.
-    fn read_dir(&self, inode: &Inode, sb: &Superblock) -> io::Result<Option<Vec<DirEntry>>> {
+    /// The entries of every block of a directory, skipping unused records.
+    fn dir_entries(&self, inode: &Inode, sb: &Superblock) -> io::Result<Option<Vec<DirEntry>>> {
         match inode.file_type() {
             FileType::Directory => {
-                let mut buf = vec![0; sb.block_size() as usize];
+                let bs = sb.block_size() as usize;
+                let mut buf = vec![0; bs];
                 let mut vec = Vec::new();
-                self.read_block(inode.i_block.0[0], &mut buf, sb)?;
-                let mut start: usize = 0;
-                while start < sb.block_size() as usize {
-                    let entry = DirEntry::new(&buf[start..sb.block_size() as usize]);
-                    start += entry.rec_len as usize;
-                    if entry.inode == 0 {
-                        assert_eq!(start, sb.block_size() as usize)
+                for block in self.dir_blocks(inode, sb)? {
+                    self.read_block(block, &mut buf, sb)?;
+                    let mut start: usize = 0;
+                    while start < bs {
+                        let entry = DirEntry::checked(&buf[start..bs], block)?;
+                        start += entry.rec_len as usize;
+                        if entry.inode != 0 {
+                            vec.push(entry);
+                        }
                     }
-                    vec.push(entry);
                 }
                 Ok(Some(vec))
             }
@@ -234,27 +541,673 @@ impl<T: disk::Disk> Ext2<T> {
         }
     }
 
-    /// Todo: Fix calculation of blocks to be read.
-    fn read_inode_data_block(
+    /// Find the first logical block of a file from block `idx` on that is
+    /// mapped to a data block, or that is not if `mapped` is false.
+    ///
+    /// Unmapped indirect blocks are skipped over whole, so searching a
+    /// sparse file does not visit every block of its holes.  Blocks past
+    /// the end of the file are not considered.
+    fn find_block(
+        &self,
+        inode: &Inode,
+        idx: u64,
+        mapped: bool,
+        sb: &Superblock,
+    ) -> io::Result<Option<u64>> {
+        let bs = sb.block_size() as u64;
+        let in_file = inode.size().div_ceil(bs);
+        let ptrs_per_block = bs / 4;
+        let mut base = 0;
+        let roots = inode.i_block.0.iter().map(|&ptr| (ptr, 0));
+        let roots = roots.chain([(inode.i_block.1, 1), (inode.i_block.2, 2), (inode.i_block.3, 3)]);
+        for (ptr, level) in roots {
+            if base >= in_file {
+                break;
+            }
+            let span = ptrs_per_block.pow(level);
+            if idx < base + span {
+                let from = idx.max(base);
+                if let Some(found) = self.find_in_tree(ptr, level, base, from, mapped, sb)? {
+                    return Ok(Some(found).filter(|&found| found < in_file));
+                }
+            }
+            base += span;
+        }
+        Ok(if mapped || idx >= in_file { None } else { Some(idx.max(base)) })
+    }
+
+    /// Search the tree of `level` levels of indirection at `ptr`, which
+    /// maps the blocks from `base`, for the first block from `from` on
+    /// that is mapped, or not, as `mapped` asks.
+    fn find_in_tree(
+        &self,
+        ptr: u32,
+        level: u32,
+        base: u64,
+        from: u64,
+        mapped: bool,
+        sb: &Superblock,
+    ) -> io::Result<Option<u64>> {
+        if ptr == 0 {
+            // The whole tree is a hole.
+            return Ok(if mapped { None } else { Some(from) });
+        }
+        if level == 0 {
+            return Ok(if mapped { Some(from) } else { None });
+        }
+        let mut buf = vec![0; sb.block_size() as usize];
+        self.read_block(ptr, &mut buf, sb)?;
+        let child_span = (sb.block_size() as u64 / 4).pow(level - 1);
+        let first = ((from - base) / child_span) as usize;
+        for (i, chunk) in buf.chunks(4).enumerate().skip(first) {
+            let child_base = base + i as u64 * child_span;
+            let child = LE::read_u32(chunk);
+            let found =
+                self.find_in_tree(child, level - 1, child_base, from.max(child_base), mapped, sb)?;
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Check that every pointer in the tree of `level` levels of
+    /// indirection at `ptr` names a block of the filesystem, so that the
+    /// tree can be freed without tripping over a damaged pointer halfway.
+    fn check_block_tree(&self, ptr: u32, level: u32, sb: &Superblock) -> io::Result<()> {
+        if ptr == 0 {
+            return Ok(());
+        }
+        if ptr < sb.s_first_data_block || ptr >= sb.s_blocks_count {
+            return Err(Ext2Error::corrupt_block("block number out of range", ptr).into());
+        }
+        if level > 0 {
+            let mut buf = vec![0; sb.block_size() as usize];
+            self.read_block(ptr, &mut buf, sb)?;
+            for chunk in buf.chunks(4) {
+                self.check_block_tree(LE::read_u32(chunk), level - 1, sb)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read up to `count` logical blocks of a file, starting at block
+    /// `idx`, into `buf`.
+    ///
+    /// Runs of blocks that are contiguous on disk are read in one go, and
+    /// holes are filled with zeros.  Reading stops early only at the end of
+    /// the file; returns the number of blocks read.
+    fn read_inode_data_blocks(
         &self,
         inode: &Inode,
         buf: &mut [u8],
         idx: u32,
+        count: u32,
         sb: &Superblock,
-    ) -> io::Result<usize> {
-        match inode.file_type() {
-            FileType::File => {
-                let ptr = self.get_block_ptr(inode, idx, sb)?;
-                if ptr == 0 {
-                    Ok(0)
-                } else {
-                    self.read_block(ptr, buf, sb)
-                        .map(|()| sb.block_size() as usize)
+    ) -> io::Result<u32> {
+        if inode.file_type() != FileType::File {
+            return Err(Ext2Error::InvalidInput("not a regular file".to_owned()).into());
+        }
+        let bs = sb.block_size() as usize;
+        let in_file = inode.size().div_ceil(bs as u64).saturating_sub(idx as u64);
+        let count = (count as u64).min(in_file) as u32;
+        let mut ptrs = Vec::with_capacity(count as usize);
+        for i in idx..idx + count {
+            ptrs.push(self.get_block_ptr(inode, i, sb)?);
+        }
+        let mut done = 0;
+        while done < ptrs.len() {
+            if ptrs[done] == 0 {
+                buf[done * bs..(done + 1) * bs].fill(0);
+                done += 1;
+                continue;
+            }
+            let run = 1 + ptrs[done + 1..]
+                .iter()
+                .zip(ptrs[done] + 1..)
+                .take_while(|&(&ptr, expected)| ptr == expected)
+                .count();
+            self.read_blocks(ptrs[done], run as u32, &mut buf[done * bs..(done + run) * bs], sb)?;
+            done += run;
+        }
+        Ok(done as u32)
+    }
+}
+
+impl<T: disk::WriteDisk> Ext2<T> {
+    /// Open a file for writing, creating it if it does not exist and
+    /// truncating it if it does.
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> io::Result<handle::Ext2Handle<'_, T>> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self, path)
+    }
+
+    /// Flush any buffered writes through to the underlying disk.
+    ///
+    /// The cached superblock and descriptors are written through as they
+    /// change, so this only needs to sync the disk; a `BlockCache` writes
+    /// back its dirty blocks when synced.
+    pub fn sync(&self) -> io::Result<()> {
+        self.disk
+            .write()
+            .expect("Got a poisoned lock.  Cannot recover")
+            .sync_disk()
+    }
+
+    /// Open `path` with `options`, resolving a relative path from `dir` if
+    /// given, or from the root if not.
+    pub(crate) fn open_with<P: AsRef<Path>>(
+        &self,
+        dir: Option<&handle::Ext2Handle<'_, T>>,
+        path: P,
+        options: &OpenOptions,
+    ) -> io::Result<handle::Ext2Handle<'_, T>> {
+        let path = path.as_ref();
+        if self.read_only && (options.write || options.append) {
+            return Err(Ext2Error::ReadOnly.into());
+        }
+        let superblock = self.superblock()?;
+        let base = open_base(dir)?;
+        let (inum, inode) = match self.lookup_from(base, path, true, &superblock)? {
+            Some((inum, mut inode)) => {
+                if options.create_new {
+                    return Err(Ext2Error::AlreadyExists(path.to_owned()).into());
+                }
+                // Only a regular file's i_block is a map of data blocks that
+                // can be written or truncated.
+                if options.write || options.append || options.truncate {
+                    match inode.file_type() {
+                        FileType::File => {}
+                        FileType::Directory => {
+                            return Err(Ext2Error::IsADirectory(path.to_owned()).into())
+                        }
+                        _ => {
+                            return Err(Ext2Error::PermissionDenied(format!(
+                                "{:?} is not a regular file",
+                                path
+                            ))
+                            .into())
+                        }
+                    }
                 }
+                if options.truncate && inode.size() > 0 {
+                    self.free_inode_blocks(&mut inode, &superblock)?;
+                    inode.set_size(0);
+                    inode.i_mtime = now();
+                    inode.i_ctime = inode.i_mtime;
+                    self.write_inode(inum, &inode, &superblock)?;
+                }
+                (inum, inode)
+            }
+            None if options.create || options.create_new => {
+                self.create_file(base, path, &superblock)?
+            }
+            None => {
+                return Err(Ext2Error::NotFound(path.to_owned()).into())
+            }
+        };
+        let path = open_path(dir, path);
+        Ok(handle::Ext2Handle::new(self, path, superblock, inum, inode).with_options(options))
+    }
+
+    /// Allocate a new, empty regular file at `path` and link it into its
+    /// parent directory.
+    fn create_file(
+        &self,
+        base: Option<(u32, &Inode)>,
+        path: &Path,
+        sb: &Superblock,
+    ) -> io::Result<(u32, Inode)> {
+        let (dir, mut dir_inode, name) = self.parent_dir_from(base, path, sb)?;
+        if self.get_inum_in_dir(&dir_inode, name, sb)?.is_some() {
+            // Only a symlink to nowhere exists without being found.
+            return Err(Ext2Error::AlreadyExists(path.to_owned()).into());
+        }
+        let inum = alloc::Allocator::new(self, sb).alloc_inode(dir, false)?;
+        let time = now();
+        let mut inode = Inode {
+            i_mode: 0x8000 | 0o644,
+            i_atime: time,
+            i_ctime: time,
+            i_mtime: time,
+            i_links_count: 1,
+            ..Inode::default()
+        };
+        self.write_new_inode(inum, &inode, sb)
+            .and_then(|()| self.add_dir_entry(&mut dir_inode, dir, name, inum, FileType::File, sb))
+            .map_err(|err| self.abandon_inode(inum, &mut inode, sb, err))?;
+        Ok((inum, inode))
+    }
+
+    fn write_block(&self, blocknum: u32, buf: &[u8], sb: &Superblock) -> io::Result<()> {
+        if self.read_only {
+            return Err(Ext2Error::ReadOnly.into());
+        }
+        let block_size = sb.block_size();
+        if buf.len() < block_size as usize {
+            return Err(Ext2Error::InvalidInput(format!(
+                "buffer of {} bytes is smaller than a block",
+                buf.len()
+            )).into());
+        }
+        self.write_bytes(block_size as u64 * blocknum as u64, &buf[..block_size as usize])
+    }
+
+    /// Write `buf` at `offset` on the disk, reading in and merging with
+    /// the rest of any sector it only partly covers.
+    fn write_bytes(&self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let mut disk = self.disk
+            .write()
+            .expect("Got a poisoned lock.  Cannot recover");
+        let ss = disk.sector_size() as u64;
+        if offset.is_multiple_of(ss) && (buf.len() as u64).is_multiple_of(ss) {
+            return disk.write_sectors(offset / ss, buf);
+        }
+        let first = offset / ss;
+        let end = (offset + buf.len() as u64).div_ceil(ss);
+        let mut sectors = vec![0; ((end - first) * ss) as usize];
+        disk.read_sectors(first, &mut sectors)?;
+        let start = (offset - first * ss) as usize;
+        sectors[start..start + buf.len()].copy_from_slice(buf);
+        disk.write_sectors(first, &sectors)
+    }
+
+    /// Write the primary superblock back to disk.
+    ///
+    /// Fields this crate does not parse are preserved.
+    fn write_superblock(&self, sb: &Superblock) -> io::Result<()> {
+        if self.read_only {
+            return Err(Ext2Error::ReadOnly.into());
+        }
+        let mut cache = self.cache
+            .write()
+            .expect("Got a poisoned lock.  Cannot recover");
+        let cache = cache.as_mut().ok_or_else(not_formatted)?;
+        let mut block = cache.raw_superblock;
+        sb.write_to(&mut block);
+        self.write_bytes(1024, &block)?;
+        cache.raw_superblock = block;
+        cache.superblock = sb.clone();
+        Ok(())
+    }
+
+    fn write_block_group_descriptor(
+        &self,
+        groupnum: u32,
+        descriptor: &BlockGroupDescriptor,
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        let bs = sb.block_size() as usize;
+        let offset = groupnum as usize * 32;
+        let mut cache = self.cache
+            .write()
+            .expect("Got a poisoned lock.  Cannot recover");
+        let cache = cache.as_mut().ok_or_else(not_formatted)?;
+        if offset + 32 > cache.descriptors.len() {
+            return Err(not_formatted());
+        }
+        let start = offset / bs * bs;
+        let mut buf = cache.descriptors[start..start + bs].to_vec();
+        descriptor.write_to(&mut buf[offset - start..offset - start + 32]);
+        let block = (offset / bs) as u32 + self.first_descriptor_block(sb);
+        self.write_block(block, &buf, sb)?;
+        cache.descriptors[start..start + bs].copy_from_slice(&buf);
+        Ok(())
+    }
+
+    /// Apply `f` to the on-disk slot of inode `iptr`, which is
+    /// `sb.inode_size()` bytes long, and write the result back.
+    fn update_inode_slot<F>(&self, iptr: u32, sb: &Superblock, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        let (igroup, ioffset) = sb.locate_inode(iptr);
+        let descriptor = self.get_block_group_descriptor(igroup, sb)?;
+        let iblock = descriptor.bg_inode_table + (ioffset * sb.inode_size()) / sb.block_size();
+        let iblock_offset = ((ioffset * sb.inode_size()) % sb.block_size()) as usize;
+        let mut buf = vec![0; sb.block_size() as usize];
+        self.read_block(iblock, &mut buf[..], sb)?;
+        f(&mut buf[iblock_offset..iblock_offset + sb.inode_size() as usize]);
+        self.write_block(iblock, &buf, sb)
+    }
+
+    fn write_inode(&self, iptr: u32, inode: &Inode, sb: &Superblock) -> io::Result<()> {
+        self.update_inode_slot(iptr, sb, |slot| inode.write_to(slot))
+    }
+
+    /// Write a freshly allocated inode, clearing whatever a previous owner of
+    /// the slot left behind.
+    fn write_new_inode(&self, iptr: u32, inode: &Inode, sb: &Superblock) -> io::Result<()> {
+        self.update_inode_slot(iptr, sb, |slot| {
+            for byte in slot.iter_mut() {
+                *byte = 0;
+            }
+            inode.write_to(slot);
+        })
+    }
+
+    /// Find the physical block backing logical block `idx` of `inode`,
+    /// allocating it and any missing indirect blocks on the way.
+    ///
+    /// New blocks are placed directly after the preceding block of the file
+    /// where possible, so that files are laid out contiguously; `goal` is the
+    /// fallback when there is no preceding block to go by.  Returns the
+    /// physical block number and whether the data block was newly allocated,
+    /// in which case its contents are undefined.  The caller is responsible
+    /// for writing `inode` back to disk.
+    ///
+    /// If an allocation fails partway, the blocks already allocated are
+    /// unlinked and freed again before the error is returned.
+    fn map_block(
+        &self,
+        inode: &mut Inode,
+        idx: u32,
+        goal: u32,
+        sb: &Superblock,
+    ) -> io::Result<(u32, bool)> {
+        let mut allocated = Vec::new();
+        self.map_block_with(inode, idx, goal, sb, &mut allocated)
+            .inspect_err(|_| {
+                // Undoing is best effort: the first error is the one to report.
+                let _ = self.unmap_blocks(inode, &allocated, sb);
+            })
+    }
+
+    /// Do the work of `map_block`, recording each block allocated in
+    /// `allocated` with the indirect block and slot that point to it, or
+    /// `None` if `inode` itself does.
+    fn map_block_with(
+        &self,
+        inode: &mut Inode,
+        idx: u32,
+        goal: u32,
+        sb: &Superblock,
+        allocated: &mut Vec<(u32, Option<(u32, usize)>)>,
+    ) -> io::Result<(u32, bool)> {
+        let allocator = alloc::Allocator::new(self, sb);
+        let ptrs_per_block = sb.block_size() / 4;
+        let direct_limit = 12;
+        let single_limit = direct_limit + ptrs_per_block;
+        let double_limit = single_limit + ptrs_per_block * ptrs_per_block;
+        let triple_limit = double_limit as u64 + (ptrs_per_block as u64).pow(3);
+        if idx as u64 >= triple_limit {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                "write beyond the largest addressable block",
+            ));
+        }
+        let (root, mut offset, level) = if idx < direct_limit {
+            (inode.i_block.0[idx as usize], 0, 0)
+        } else if idx < single_limit {
+            (inode.i_block.1, idx - direct_limit, 1)
+        } else if idx < double_limit {
+            (inode.i_block.2, idx - single_limit, 2)
+        } else {
+            (inode.i_block.3, idx - double_limit, 3)
+        };
+        let mut fresh = false;
+        let mut ptr = root;
+        if ptr == 0 {
+            let previous = inode.i_block.0[(idx.min(direct_limit) as usize).saturating_sub(1)];
+            let near = if idx > 0 && previous != 0 { previous + 1 } else { goal };
+            ptr = allocator.alloc_block(near)?;
+            allocated.push((ptr, None));
+            match level {
+                0 => inode.i_block.0[idx as usize] = ptr,
+                1 => inode.i_block.1 = ptr,
+                2 => inode.i_block.2 = ptr,
+                _ => inode.i_block.3 = ptr,
+            }
+            if level > 0 {
+                self.write_block(ptr, &vec![0; sb.block_size() as usize], sb)?;
+            }
+            fresh = true;
+        }
+        let mut buf = vec![0; sb.block_size() as usize];
+        for level in (1..=level).rev() {
+            let ptrs_per_bucket = ptrs_per_block.pow(level - 1);
+            let index = (offset / ptrs_per_bucket) as usize;
+            offset %= ptrs_per_bucket;
+            self.read_block(ptr, &mut buf, sb)?;
+            let mut next = LE::read_u32(&buf[index * 4..(index + 1) * 4]);
+            fresh = false;
+            if next == 0 {
+                let previous = match index {
+                    0 => 0,
+                    _ => LE::read_u32(&buf[(index - 1) * 4..index * 4]),
+                };
+                let near = if previous != 0 { previous + 1 } else { ptr + 1 };
+                next = allocator.alloc_block(near)?;
+                allocated.push((next, Some((ptr, index))));
+                if level > 1 {
+                    self.write_block(next, &vec![0; sb.block_size() as usize], sb)?;
+                }
+                LE::write_u32(&mut buf[index * 4..(index + 1) * 4], next);
+                self.write_block(ptr, &buf, sb)?;
+                fresh = true;
+            }
+            ptr = next;
+        }
+        inode.i_blocks += allocated.len() as u32 * (sb.block_size() / 512);
+        Ok((ptr, fresh))
+    }
+
+    /// Undo a failed `map_block`: clear the pointers to the blocks it
+    /// `allocated`, and free them.
+    fn unmap_blocks(
+        &self,
+        inode: &mut Inode,
+        allocated: &[(u32, Option<(u32, usize)>)],
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        let allocator = alloc::Allocator::new(self, sb);
+        let mut buf = vec![0; sb.block_size() as usize];
+        for &(block, parent) in allocated.iter().rev() {
+            match parent {
+                // A parent allocated by the same call is freed anyway.
+                Some((parent, _)) if allocated.iter().any(|&(b, _)| b == parent) => {}
+                Some((parent, index)) => {
+                    self.read_block(parent, &mut buf, sb)?;
+                    LE::write_u32(&mut buf[index * 4..(index + 1) * 4], 0);
+                    self.write_block(parent, &buf, sb)?;
+                }
+                None => {
+                    let roots = [&mut inode.i_block.1, &mut inode.i_block.2, &mut inode.i_block.3];
+                    for ptr in inode.i_block.0.iter_mut().chain(roots) {
+                        if *ptr == block {
+                            *ptr = 0;
+                        }
+                    }
+                }
+            }
+            allocator.free_block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Release every data and indirect block owned by `inode`.
+    ///
+    /// The caller is responsible for updating the size and writing `inode`
+    /// back to disk.
+    fn free_inode_blocks(&self, inode: &mut Inode, sb: &Superblock) -> io::Result<()> {
+        for ptr in inode.i_block.0.iter_mut() {
+            self.free_block_tree(*ptr, 0, sb)?;
+            *ptr = 0;
+        }
+        self.free_block_tree(inode.i_block.1, 1, sb)?;
+        self.free_block_tree(inode.i_block.2, 2, sb)?;
+        self.free_block_tree(inode.i_block.3, 3, sb)?;
+        inode.i_block.1 = 0;
+        inode.i_block.2 = 0;
+        inode.i_block.3 = 0;
+        inode.i_blocks = 0;
+        Ok(())
+    }
+
+    fn free_block_tree(&self, ptr: u32, level: u32, sb: &Superblock) -> io::Result<()> {
+        if ptr == 0 {
+            return Ok(());
+        }
+        if level > 0 {
+            let mut buf = vec![0; sb.block_size() as usize];
+            self.read_block(ptr, &mut buf, sb)?;
+            for chunk in buf.chunks(4) {
+                self.free_block_tree(LE::read_u32(chunk), level - 1, sb)?;
+            }
+        }
+        alloc::Allocator::new(self, sb).free_block(ptr)
+    }
+
+    /// Mark the file system as containing files larger than 2GiB.
+    fn set_large_file(&self) -> io::Result<()> {
+        let _guard = self.meta
+            .lock()
+            .expect("Got a poisoned mutex.  Cannot recover");
+        let mut current = self.superblock()?;
+        if !current.ro_compat_features().contains(RoCompatFeatures::LARGE_FILE) {
+            current.s_feature_ro_compat |= RoCompatFeatures::LARGE_FILE.bits();
+            self.write_superblock(&current)?;
+        }
+        Ok(())
+    }
+
+    /// Write up to one block of `buf` into the file `inum` at byte `pos`.
+    ///
+    /// Returns the number of bytes written.  The size and timestamps of
+    /// `inode` are updated, and it is written back to disk.
+    fn write_inode_data(
+        &self,
+        inum: u32,
+        inode: &mut Inode,
+        buf: &[u8],
+        pos: u64,
+        sb: &Superblock,
+    ) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let bs = sb.block_size() as u64;
+        let idx = pos / bs;
+        if idx > u32::MAX as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                "write beyond the largest addressable block",
+            ));
+        }
+        let offset = (pos % bs) as usize;
+        let len = buf.len().min(bs as usize - offset);
+        let goal = sb.group_first_block(sb.locate_inode(inum).0);
+        let (ptr, fresh) = self.map_block(inode, idx as u32, goal, sb)?;
+        let mut block = vec![0; bs as usize];
+        if !fresh && len < bs as usize {
+            self.read_block(ptr, &mut block, sb)?;
+        }
+        block[offset..offset + len].copy_from_slice(&buf[..len]);
+        self.write_block(ptr, &block, sb)?;
+        let end = pos + len as u64;
+        if end > inode.size() {
+            if end > i32::MAX as u64 {
+                self.set_large_file()?;
+            }
+            inode.set_size(end);
+        }
+        inode.i_mtime = now();
+        inode.i_ctime = inode.i_mtime;
+        self.write_inode(inum, inode, sb)?;
+        Ok(len)
+    }
+}
+
+/// A directory or file passed through while resolving a path.
+#[derive(Clone)]
+struct Step {
+    name: OsString,
+    inum: u32,
+    inode: Inode,
+}
+
+/// A path component still to be resolved.
+enum Pending {
+    ParentDir,
+    Normal(OsString),
+}
+
+/// Read `buf.len()` bytes from `offset` with `read`, which can only read
+/// whole sectors of `sector_size` bytes.
+fn read_unaligned<F>(sector_size: u32, offset: u64, buf: &mut [u8], mut read: F) -> io::Result<()>
+where
+    F: FnMut(u64, &mut [u8]) -> io::Result<()>,
+{
+    let ss = sector_size as u64;
+    if offset.is_multiple_of(ss) && (buf.len() as u64).is_multiple_of(ss) {
+        return read(offset / ss, buf);
+    }
+    let first = offset / ss;
+    let end = (offset + buf.len() as u64).div_ceil(ss);
+    let mut sectors = vec![0; ((end - first) * ss) as usize];
+    read(first, &mut sectors)?;
+    let start = (offset - first * ss) as usize;
+    buf.copy_from_slice(&sectors[start..start + buf.len()]);
+    Ok(())
+}
+
+/// The directory a relative path given alongside `dir` starts from.
+fn open_base<'h, T: disk::ReadDisk>(
+    dir: Option<&'h handle::Ext2Handle<'_, T>>,
+) -> io::Result<Option<(u32, &'h Inode)>> {
+    match dir {
+        Some(dir) if dir.inode().file_type() != FileType::Directory => {
+            Err(Ext2Error::NotADirectory(dir.path().to_owned()).into())
+        }
+        Some(dir) => Ok(Some((dir.inum(), dir.inode()))),
+        None => Ok(None),
+    }
+}
+
+/// The path a handle opened at `path` from `dir` reports.
+fn open_path<T: disk::ReadDisk>(dir: Option<&handle::Ext2Handle<'_, T>>, path: &Path) -> PathBuf {
+    match dir {
+        Some(dir) => dir.path().join(path),
+        None => path.to_owned(),
+    }
+}
+
+/// Push the components of `path` onto `pending` so that the first is popped
+/// first.  The root and `.` are left out, as the caller handles those.
+fn push_components(pending: &mut Vec<Pending>, path: &Path) -> io::Result<()> {
+    let start = pending.len();
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) => {
+                return Err(Ext2Error::InvalidInput(format!(
+                    "{:?} has a prefix, which ext2 paths cannot have",
+                    path
+                )).into());
             }
-            _ => Err(io::Error::new(io::ErrorKind::Other, "Not found")),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => pending.push(Pending::ParentDir),
+            Component::Normal(name) => pending.push(Pending::Normal(name.to_owned())),
         }
     }
+    pending[start..].reverse();
+    Ok(())
+}
+
+/// The error for using the metadata of a filesystem before `Mkfs` has
+/// finished writing it.
+fn not_formatted() -> io::Error {
+    Ext2Error::InvalidInput("the filesystem has not been formatted".to_owned()).into()
+}
+
+/// The current time as an ext2 timestamp.
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
 }
 
 /// Ext2 superblock struct.
@@ -319,6 +1272,9 @@ pub struct Superblock {
 
 impl Superblock {
     pub fn new(data: &[u8]) -> io::Result<Superblock> {
+        if data.len() < 264 {
+            return Err(Ext2Error::corrupt("truncated superblock").into());
+        }
         Ok(Superblock {
             s_inodes_count: LE::read_u32(&data[0..4]),
             s_blocks_count: LE::read_u32(&data[4..8]), //LE::read_u32(&d[])?,
@@ -379,9 +1335,129 @@ impl Superblock {
         })
     }
 
+    /// The raw 1024 byte superblock, with zeros in the fields this crate
+    /// does not parse.
+    pub fn to_bytes(&self) -> [u8; 1024] {
+        let mut data = [0; 1024];
+        self.write_to(&mut data);
+        data
+    }
+
+    /// Write the superblock fields into `data`, the raw 1024 byte superblock.
+    ///
+    /// Bytes beyond the fields parsed by `Superblock::new` are left untouched.
+    pub fn write_to(&self, data: &mut [u8]) {
+        LE::write_u32(&mut data[0..4], self.s_inodes_count);
+        LE::write_u32(&mut data[4..8], self.s_blocks_count);
+        LE::write_u32(&mut data[8..12], self.s_r_blocks_count);
+        LE::write_u32(&mut data[12..16], self.s_free_blocks_count);
+        LE::write_u32(&mut data[16..20], self.s_free_inodes_count);
+        LE::write_u32(&mut data[20..24], self.s_first_data_block);
+        LE::write_u32(&mut data[24..28], self.s_log_block_size);
+        LE::write_u32(&mut data[28..32], self.s_log_frag_size);
+        LE::write_u32(&mut data[32..36], self.s_blocks_per_group);
+        LE::write_u32(&mut data[36..40], self.s_frags_per_group);
+        LE::write_u32(&mut data[40..44], self.s_inodes_per_group);
+        LE::write_u32(&mut data[44..48], self.s_mtime);
+        LE::write_u32(&mut data[48..52], self.s_wtime);
+        LE::write_u16(&mut data[52..54], self.s_mnt_count);
+        LE::write_u16(&mut data[54..56], self.s_max_mnt_count);
+        LE::write_u16(&mut data[56..58], self.s_magic);
+        LE::write_u16(&mut data[58..60], self.s_state);
+        LE::write_u16(&mut data[60..62], self.s_errors);
+        LE::write_u16(&mut data[62..64], self.s_minor_rev_level);
+        LE::write_u32(&mut data[64..68], self.s_lastcheck);
+        LE::write_u32(&mut data[68..72], self.s_checkinterval);
+        LE::write_u32(&mut data[72..76], self.s_creator_os);
+        LE::write_u32(&mut data[76..80], self.s_rev_level);
+        LE::write_u16(&mut data[80..82], self.s_def_resuid);
+        LE::write_u16(&mut data[82..84], self.s_def_resgid);
+        LE::write_u32(&mut data[84..88], self.s_first_ino);
+        LE::write_u16(&mut data[88..90], self.s_inode_size);
+        LE::write_u16(&mut data[90..92], self.s_block_group_nr);
+        LE::write_u32(&mut data[92..96], self.s_feature_compat);
+        LE::write_u32(&mut data[96..100], self.s_feature_incompat);
+        LE::write_u32(&mut data[100..104], self.s_feature_ro_compat);
+        data[104..120].copy_from_slice(&self.s_uuid);
+        data[120..136].copy_from_slice(&self.s_volume_name);
+        data[136..168].copy_from_slice(&self.s_last_mounted.0[0]);
+        data[168..200].copy_from_slice(&self.s_last_mounted.0[1]);
+        LE::write_u32(&mut data[200..204], self.s_algo_bitmap);
+        // Performance hints
+        data[204] = self.s_prealloc_blocks;
+        data[205] = self.s_prealloc_dir_blocks;
+        data[206] = self._align.0;
+        data[207] = self._align.1;
+        // Journaling support
+        data[208..224].copy_from_slice(&self.s_journal_uuid);
+        LE::write_u32(&mut data[224..228], self.s_journal_inum);
+        LE::write_u32(&mut data[228..232], self.s_journal_dev);
+        LE::write_u32(&mut data[232..236], self.s_last_orphan);
+        // Directory indexing support
+        LE::write_u32(&mut data[236..240], self.s_hash_seed[0]);
+        LE::write_u32(&mut data[240..244], self.s_hash_seed[1]);
+        LE::write_u32(&mut data[244..248], self.s_hash_seed[2]);
+        LE::write_u32(&mut data[248..252], self.s_hash_seed[3]);
+        data[252] = self.s_def_hash_version;
+        data[253] = self._hash_version_align.0;
+        data[254] = self._hash_version_align.1;
+        data[255] = self._hash_version_align.2;
+        // Other options
+        LE::write_u32(&mut data[256..260], self.s_default_mount_options);
+        LE::write_u32(&mut data[260..264], self.s_first_meta_bg);
+    }
+
+    /// Check that the superblock describes a filesystem we can make sense
+    /// of, so that later arithmetic on it cannot divide by zero or overflow.
+    pub fn check(&self) -> io::Result<()> {
+        if self.s_magic != EXT2_SUPER_MAGIC {
+            return Err(Ext2Error::corrupt(format!(
+                "bad magic number 0x{:04x}; not an ext2 filesystem",
+                self.s_magic
+            )).into());
+        }
+        if let Revision::Unknown(rev) = self.revision() {
+            return Err(Ext2Error::Unsupported(format!("revision {}", rev)).into());
+        }
+        let corrupt = |what: &str| -> io::Result<()> {
+            Err(Ext2Error::corrupt(format!("superblock has {}", what)).into())
+        };
+        if self.s_log_block_size > EXT2_MAX_BLOCK_LOG_SIZE {
+            return corrupt("an impossible block size");
+        }
+        let bs = self.block_size();
+        if self.s_first_data_block != if bs == 1024 { 1 } else { 0 } {
+            return corrupt("the wrong first data block");
+        }
+        let max_per_group = (bs * 8).min(mkfs::MAX_PER_GROUP);
+        if self.s_blocks_per_group == 0 || self.s_blocks_per_group > max_per_group {
+            return corrupt("an impossible number of blocks per group");
+        }
+        if self.s_inodes_per_group == 0 || self.s_inodes_per_group > max_per_group {
+            return corrupt("an impossible number of inodes per group");
+        }
+        if self.s_blocks_count <= self.s_first_data_block || self.s_inodes_count == 0 {
+            return corrupt("no blocks or no inodes");
+        }
+        let block_groups = (self.s_blocks_count - self.s_first_data_block - 1)
+            / self.s_blocks_per_group
+            + 1;
+        if block_groups != self.block_group_count() {
+            return corrupt("block and inode counts that disagree on the group count");
+        }
+        let inode_size = self.inode_size();
+        if inode_size < 128 || !inode_size.is_power_of_two() || inode_size > bs {
+            return corrupt("an impossible inode size");
+        }
+        if self.first_ino() < 3 || self.first_ino() > self.s_inodes_count {
+            return corrupt("an impossible first inode");
+        }
+        Ok(())
+    }
+
     pub fn block_group_count(&self) -> u32 {
         self.s_inodes_count / self.s_inodes_per_group
-            + if self.s_inodes_count % self.s_inodes_per_group == 0 {
+            + if self.s_inodes_count.is_multiple_of(self.s_inodes_per_group) {
                 0
             } else {
                 1
@@ -394,10 +1470,24 @@ impl Superblock {
         (index, offset)
     }
 
+    /// The first block belonging to block group `group`.
+    pub fn group_first_block(&self, group: u32) -> u32 {
+        self.s_first_data_block + group * self.s_blocks_per_group
+    }
+
     pub fn block_size(&self) -> u32 {
         1024 << self.s_log_block_size
     }
 
+    /// The first inode number available to regular files.
+    pub fn first_ino(&self) -> u32 {
+        if self.s_rev_level > 0 {
+            self.s_first_ino
+        } else {
+            11
+        }
+    }
+
     pub fn inode_size(&self) -> u32 {
         if self.s_rev_level > 0 {
             self.s_inode_size as u32
@@ -405,6 +1495,164 @@ impl Superblock {
             128
         }
     }
+
+    pub fn compat_features(&self) -> CompatFeatures {
+        CompatFeatures::from_bits_retain(self.s_feature_compat)
+    }
+
+    pub fn incompat_features(&self) -> IncompatFeatures {
+        IncompatFeatures::from_bits_retain(self.s_feature_incompat)
+    }
+
+    pub fn ro_compat_features(&self) -> RoCompatFeatures {
+        RoCompatFeatures::from_bits_retain(self.s_feature_ro_compat)
+    }
+
+    pub fn state(&self) -> State {
+        State::from_raw(self.s_state)
+    }
+
+    pub fn errors(&self) -> ErrorBehavior {
+        ErrorBehavior::from_raw(self.s_errors)
+    }
+
+    pub fn creator_os(&self) -> CreatorOs {
+        CreatorOs::from_raw(self.s_creator_os)
+    }
+
+    pub fn revision(&self) -> Revision {
+        Revision::from_raw(self.s_rev_level)
+    }
+}
+
+/// Whether the filesystem was cleanly unmounted, and whether errors have
+/// been found on it (`s_state`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct State {
+    pub clean: bool,
+    pub errors: bool,
+}
+
+impl State {
+    /// Unmounted cleanly.
+    const VALID_FS: u16 = 0x0001;
+    /// Errors were detected.
+    const ERROR_FS: u16 = 0x0002;
+
+    fn from_raw(state: u16) -> State {
+        State {
+            clean: state & State::VALID_FS != 0,
+            errors: state & State::ERROR_FS != 0,
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(if self.clean { "clean" } else { "not clean" })?;
+        if self.errors {
+            f.write_str(" with errors")?;
+        }
+        Ok(())
+    }
+}
+
+/// What the kernel should do on finding an error (`s_errors`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorBehavior {
+    Continue,
+    RemountReadOnly,
+    Panic,
+    Unknown(u16),
+}
+
+impl ErrorBehavior {
+    fn from_raw(errors: u16) -> ErrorBehavior {
+        match errors {
+            1 => ErrorBehavior::Continue,
+            2 => ErrorBehavior::RemountReadOnly,
+            3 => ErrorBehavior::Panic,
+            x => ErrorBehavior::Unknown(x),
+        }
+    }
+}
+
+impl fmt::Display for ErrorBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorBehavior::Continue => f.write_str("Continue"),
+            ErrorBehavior::RemountReadOnly => f.write_str("Remount read-only"),
+            ErrorBehavior::Panic => f.write_str("Panic"),
+            ErrorBehavior::Unknown(x) => write!(f, "Unknown ({})", x),
+        }
+    }
+}
+
+/// The operating system that created the filesystem (`s_creator_os`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CreatorOs {
+    Linux,
+    Hurd,
+    Masix,
+    FreeBSD,
+    Lites,
+    Unknown(u32),
+}
+
+impl CreatorOs {
+    fn from_raw(os: u32) -> CreatorOs {
+        match os {
+            0 => CreatorOs::Linux,
+            1 => CreatorOs::Hurd,
+            2 => CreatorOs::Masix,
+            3 => CreatorOs::FreeBSD,
+            4 => CreatorOs::Lites,
+            x => CreatorOs::Unknown(x),
+        }
+    }
+}
+
+impl fmt::Display for CreatorOs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CreatorOs::Linux => f.write_str("Linux"),
+            CreatorOs::Hurd => f.write_str("GNU/Hurd"),
+            CreatorOs::Masix => f.write_str("Masix"),
+            CreatorOs::FreeBSD => f.write_str("FreeBSD"),
+            CreatorOs::Lites => f.write_str("Lites"),
+            CreatorOs::Unknown(x) => write!(f, "(unknown os {})", x),
+        }
+    }
+}
+
+/// The revision of the on-disk format (`s_rev_level`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Revision {
+    /// Fixed 128-byte inodes and no feature flags.
+    GoodOld,
+    /// Variable inode sizes and feature flags.
+    Dynamic,
+    Unknown(u32),
+}
+
+impl Revision {
+    fn from_raw(rev: u32) -> Revision {
+        match rev {
+            0 => Revision::GoodOld,
+            1 => Revision::Dynamic,
+            x => Revision::Unknown(x),
+        }
+    }
+}
+
+impl fmt::Display for Revision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Revision::GoodOld => f.write_str("0 (original)"),
+            Revision::Dynamic => f.write_str("1 (dynamic)"),
+            Revision::Unknown(x) => write!(f, "{} (unknown)", x),
+        }
+    }
 }
 
 #[repr(C)]
@@ -422,8 +1670,8 @@ pub struct BlockGroupDescriptor {
 
 impl BlockGroupDescriptor {
     pub fn new(data: &[u8]) -> io::Result<BlockGroupDescriptor> {
-        if data.len() != 32 {
-            panic!("BlockGroupDescriptors must be 32 bytes in length");
+        if data.len() < 32 {
+            return Err(Ext2Error::corrupt("truncated block group descriptor").into());
         }
         Ok(BlockGroupDescriptor {
             bg_block_bitmap: LE::read_u32(&data[0..4]),
@@ -436,6 +1684,24 @@ impl BlockGroupDescriptor {
             bg_reserved: array::array12(&data[20..32]),
         })
     }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut data = [0; 32];
+        self.write_to(&mut data);
+        data
+    }
+
+    /// Write the descriptor into `data`, which must be 32 bytes long.
+    pub fn write_to(&self, data: &mut [u8]) {
+        LE::write_u32(&mut data[0..4], self.bg_block_bitmap);
+        LE::write_u32(&mut data[4..8], self.bg_inode_bitmap);
+        LE::write_u32(&mut data[8..12], self.bg_inode_table);
+        LE::write_u16(&mut data[12..14], self.bg_free_blocks_count);
+        LE::write_u16(&mut data[14..16], self.bg_free_inodes_count);
+        LE::write_u16(&mut data[16..18], self.bg_used_dirs_count);
+        LE::write_u16(&mut data[18..20], self.bg_pad);
+        data[20..32].copy_from_slice(&self.bg_reserved);
+    }
 }
 
 #[repr(C)]
@@ -463,6 +1729,9 @@ pub struct Inode {
 
 impl Inode {
     pub fn new(data: &[u8]) -> io::Result<Inode> {
+        if data.len() < 128 {
+            return Err(Ext2Error::corrupt("truncated inode").into());
+        }
         Ok(Inode {
             i_mode: LE::read_u16(&data[0..2]),
             i_uid: LE::read_u16(&data[2..4]),
@@ -503,6 +1772,42 @@ impl Inode {
         })
     }
 
+    /// The 128 bytes of a revision 0 inode.
+    pub fn to_bytes(&self) -> [u8; 128] {
+        let mut data = [0; 128];
+        self.write_to(&mut data);
+        data
+    }
+
+    /// Write the inode into the first 128 bytes of `data`.
+    ///
+    /// Any extra space in a larger on-disk inode is left untouched.
+    pub fn write_to(&self, data: &mut [u8]) {
+        LE::write_u16(&mut data[0..2], self.i_mode);
+        LE::write_u16(&mut data[2..4], self.i_uid);
+        LE::write_u32(&mut data[4..8], self.i_size);
+        LE::write_u32(&mut data[8..12], self.i_atime);
+        LE::write_u32(&mut data[12..16], self.i_ctime);
+        LE::write_u32(&mut data[16..20], self.i_mtime);
+        LE::write_u32(&mut data[20..24], self.i_dtime);
+        LE::write_u16(&mut data[24..26], self.i_gid);
+        LE::write_u16(&mut data[26..28], self.i_links_count);
+        LE::write_u32(&mut data[28..32], self.i_blocks);
+        LE::write_u32(&mut data[32..36], self.i_flags);
+        LE::write_u32(&mut data[36..40], self.i_osd1);
+        for (i, ptr) in self.i_block.0.iter().enumerate() {
+            LE::write_u32(&mut data[40 + i * 4..44 + i * 4], *ptr);
+        }
+        LE::write_u32(&mut data[88..92], self.i_block.1);
+        LE::write_u32(&mut data[92..96], self.i_block.2);
+        LE::write_u32(&mut data[96..100], self.i_block.3);
+        LE::write_u32(&mut data[100..104], self.i_generation);
+        LE::write_u32(&mut data[104..108], self.i_file_acl);
+        LE::write_u32(&mut data[108..112], self.i_dir_acl);
+        LE::write_u32(&mut data[112..116], self.i_faddr);
+        data[116..128].copy_from_slice(&self.i_osd2);
+    }
+
     pub fn file_type(&self) -> FileType {
         use FileType::*;
         match self.i_mode & 0xf000 {
@@ -513,7 +1818,7 @@ impl Inode {
             0x8000 => File,
             0xa000 => SymLink,
             0xc000 => UnixSocket,
-            x => panic!("Invalid file_type: 0x{:x}", x),
+            _ => Unknown,
         }
     }
 
@@ -521,6 +1826,30 @@ impl Inode {
         ((self.i_dir_acl as u64) << 32) + self.i_size as u64
     }
 
+    pub fn set_size(&mut self, size: u64) {
+        self.i_size = size as u32;
+        self.i_dir_acl = (size >> 32) as u32;
+    }
+
+    /// Whether this is a symlink short enough to keep its target in
+    /// `i_block` rather than in a data block.  As in Linux, the link is fast
+    /// if it holds no blocks besides its extended attribute block.
+    pub fn is_fast_symlink(&self, sb: &Superblock) -> bool {
+        let xattr_blocks = if self.i_file_acl != 0 { 2 << sb.s_log_block_size } else { 0 };
+        self.file_type() == FileType::SymLink && self.i_blocks == xattr_blocks
+    }
+
+    /// Whether `i_block` maps data blocks.  Devices keep their device
+    /// number there instead, and fast symlinks their target; FIFOs and
+    /// sockets have no data at all.
+    pub fn has_block_map(&self, sb: &Superblock) -> bool {
+        match self.file_type() {
+            FileType::File | FileType::Directory => true,
+            FileType::SymLink => !self.is_fast_symlink(sb),
+            _ => false,
+        }
+    }
+
     pub fn block_count(&self, sb: &Superblock) -> u32 {
         self.i_blocks / (2 << sb.s_log_block_size)
     }
@@ -530,22 +1859,83 @@ impl Inode {
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct DirEntry {
     pub inode: u32,
-    pub rec_len: u16,
+    /// The length of the record, decoded from its 16-bit form on disk.
+    pub rec_len: u32,
     pub name_len: u8,
     pub file_type: u8,
     pub name: OsString, // Should this be OsString?
 }
 
 impl DirEntry {
-    pub fn new(data: &[u8]) -> DirEntry {
+    /// Parse the entry at the start of `data`, which `checked` has made
+    /// sure holds all of it.
+    fn new(data: &[u8]) -> DirEntry {
         DirEntry {
             inode: LE::read_u32(&data[0..4]),
-            rec_len: LE::read_u16(&data[4..6]),
+            rec_len: rec_len_from_disk(LE::read_u16(&data[4..6])),
             name_len: data[6],
             file_type: data[7],
             name: OsStr::from_bytes(&data[8..8 + data[6] as usize]).to_os_string(),
         }
     }
+
+    /// Parse the entry at the start of `data`, the remainder of directory
+    /// block `block`, checking that it lies within the block.
+    pub(crate) fn checked(data: &[u8], block: u32) -> io::Result<DirEntry> {
+        if data.len() >= 8 {
+            let rec_len = rec_len_from_disk(LE::read_u16(&data[4..6])) as usize;
+            if rec_len >= 8
+                && rec_len <= data.len()
+                && rec_len.is_multiple_of(4)
+                && 8 + data[6] as usize <= rec_len
+            {
+                return Ok(DirEntry::new(data));
+            }
+        }
+        Err(Ext2Error::corrupt_block("invalid directory entry", block).into())
+    }
+
+    /// The whole record, `rec_len` bytes long (or long enough for the name,
+    /// if that is longer), with zeros after the name.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0; self.min_rec_len().max(self.rec_len as usize)];
+        self.write_to(&mut data);
+        data
+    }
+
+    /// Write the entry into the start of `data`.
+    ///
+    /// Only the header and name are written; the slack up to `rec_len` is
+    /// left untouched.
+    pub fn write_to(&self, data: &mut [u8]) {
+        let name = self.name.as_bytes();
+        LE::write_u32(&mut data[0..4], self.inode);
+        LE::write_u16(&mut data[4..6], rec_len_to_disk(self.rec_len));
+        data[6] = self.name_len;
+        data[7] = self.file_type;
+        data[8..8 + name.len()].copy_from_slice(name);
+    }
+
+    /// The smallest record length that can hold this entry: an eight byte
+    /// header plus the name, padded to a multiple of four bytes.
+    pub fn min_rec_len(&self) -> usize {
+        (8 + self.name_len as usize + 3) & !3
+    }
+}
+
+fn rec_len_from_disk(rec_len: u16) -> u32 {
+    match rec_len {
+        0 | EXT2_MAX_REC_LEN => 1 << 16,
+        rec_len => rec_len as u32,
+    }
+}
+
+fn rec_len_to_disk(rec_len: u32) -> u16 {
+    if rec_len >= 1 << 16 {
+        EXT2_MAX_REC_LEN
+    } else {
+        rec_len as u16
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -566,7 +1956,7 @@ pub struct FsPath([[u8; 32]; 2]);
 
 impl FsPath {
     pub fn new(val: [u8; 64]) -> FsPath {
-        FsPath(unsafe { std::mem::transmute(val) })
+        FsPath(unsafe { std::mem::transmute::<[u8; 64], [[u8; 32]; 2]>(val) })
     }
 
     /// Iterator over the bytes before the first null byte.
@@ -666,6 +2056,34 @@ mod tests {
         assert_eq!(superblock, expected)
     }
 
+    #[test]
+    fn basic_features() {
+        let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
+        let superblock = fs.superblock().unwrap();
+        assert_eq!(
+            superblock.compat_features(),
+            CompatFeatures::EXT_ATTR | CompatFeatures::RESIZE_INODE | CompatFeatures::DIR_INDEX
+        );
+        assert_eq!(superblock.incompat_features(), IncompatFeatures::FILETYPE);
+        assert_eq!(
+            superblock.ro_compat_features().to_string(),
+            "sparse_super large_file"
+        );
+        assert_eq!(
+            superblock.compat_features().to_string(),
+            "ext_attr resize_inode dir_index"
+        );
+        assert_eq!(
+            IncompatFeatures::from_bits_retain(0x80 | 1 << 31).to_string(),
+            "64bit FEATURE_I31"
+        );
+        assert_eq!(superblock.state().to_string(), "clean");
+        assert_eq!(superblock.errors(), ErrorBehavior::Continue);
+        assert_eq!(superblock.creator_os(), CreatorOs::Linux);
+        assert_eq!(superblock.revision(), Revision::Dynamic);
+        assert_eq!(superblock.revision().to_string(), "1 (dynamic)");
+    }
+
     #[test]
     fn basic_descriptor() {
         let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
@@ -681,12 +2099,9 @@ mod tests {
             bg_pad: 4,
             bg_reserved: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         };
-        assert_eq!(descriptor, Some(expected));
-        assert!(
-            fs.get_block_group_descriptor(9999, &superblock)
-                .unwrap()
-                .is_none()
-        );
+        assert_eq!(descriptor, expected);
+        assert!(fs.get_block_group_descriptor(1, &superblock).is_err());
+        assert!(fs.get_block_group_descriptor(9999, &superblock).is_err());
     }
 
     #[test]
@@ -723,7 +2138,7 @@ mod tests {
         let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
         let superblock = fs.superblock().unwrap();
         let inode = fs.get_root_directory(&superblock).unwrap();
-        let entries = fs.read_dir(&inode, &superblock).unwrap().unwrap();
+        let entries = fs.dir_entries(&inode, &superblock).unwrap().unwrap();
         let expected = DirEntry {
             inode: 2,
             rec_len: 12,
@@ -745,7 +2160,7 @@ mod tests {
         let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
         let superblock = fs.superblock().unwrap();
         let inode = fs.get_root_directory(&superblock).unwrap();
-        let entries = fs.read_dir(&inode, &superblock).unwrap().unwrap();
+        let entries = fs.dir_entries(&inode, &superblock).unwrap().unwrap();
         let file_entry = entries
             .into_iter()
             .find(|entry| entry.file_type == FileType::File as u8)
@@ -778,15 +2193,13 @@ mod tests {
             i_faddr: 0,
             i_osd2: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         };
-        let file_inode = fs.get_inode(file_entry.inode, &superblock)
-            .unwrap()
-            .unwrap();
+        let file_inode = fs.get_inode(file_entry.inode, &superblock).unwrap();
         assert_eq!(file_inode, expected_inode);
         assert_eq!(superblock.block_size(), 4096);
         let mut data = vec![0; 4096];
-        let read = fs.read_inode_data_block(&file_inode, &mut data, 0, &superblock)
+        let read = fs.read_inode_data_blocks(&file_inode, &mut data, 0, 1, &superblock)
             .unwrap();
-        assert_eq!(read, 4096);
+        assert_eq!(read, 1);
         assert_eq!(&String::from_utf8(data).unwrap()[..13], "Hello world!\n");
     }
 
@@ -795,12 +2208,10 @@ mod tests {
         let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
         let superblock = fs.superblock().unwrap();
         assert_eq!(
-            fs.get_inode_from_abspath("/", &superblock)
-                .unwrap()
-                .unwrap(),
+            fs.lookup("/", &superblock).unwrap().unwrap().1,
             fs.get_root_directory(&superblock).unwrap(),
         );
-        let inode = fs.get_inode_from_abspath("/sub/michelle.jpg", &superblock)
+        let (_, inode) = fs.lookup("/sub/michelle.jpg", &superblock)
             .unwrap()
             .unwrap();
         let obama_portrait = Inode {
@@ -830,7 +2241,7 @@ mod tests {
     fn get_pattern_inode() {
         let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
         let superblock = fs.superblock().unwrap();
-        let inode = fs.get_inode_from_abspath("/sub/pattern/test_pattern.txt", &superblock)
+        let (_, inode) = fs.lookup("/sub/pattern/test_pattern.txt", &superblock)
             .unwrap()
             .unwrap();
         let test_pattern = Inode {
@@ -864,4 +2275,116 @@ mod tests {
         fs.read_block(35, &mut buf, &superblock).unwrap();
         assert_eq!(&mut buf[..8], b"3 ......");
     }
+
+    /// A xorshift generator, for filling structures with arbitrary bytes.
+    fn random_bytes(state: &mut u64, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                *state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn superblock_round_trip() {
+        let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
+        let raw = fs.read_superblock_bytes().unwrap();
+        let superblock = Superblock::new(&raw).unwrap();
+        assert_eq!(&superblock.to_bytes()[..264], &raw[..264]);
+        assert_eq!(Superblock::new(&superblock.to_bytes()).unwrap(), superblock);
+        let mut patched = raw;
+        superblock.write_to(&mut patched);
+        assert_eq!(&patched[..], &raw[..]);
+    }
+
+    #[test]
+    fn refresh_rereads_metadata() {
+        let image = std::fs::read("./basic.ext2").unwrap();
+        let fs = Ext2::new(std::io::Cursor::new(image)).unwrap();
+        {
+            let mut disk = fs.disk.write().unwrap();
+            let image = disk.get_mut();
+            LE::write_u32(&mut image[1024 + 12..1024 + 16], 7);
+            LE::write_u16(&mut image[4096 + 12..4096 + 14], 7);
+        }
+        let superblock = fs.superblock().unwrap();
+        assert_eq!(superblock.s_free_blocks_count, 12);
+        fs.refresh().unwrap();
+        let superblock = fs.superblock().unwrap();
+        assert_eq!(superblock.s_free_blocks_count, 7);
+        let descriptor = fs.get_block_group_descriptor(0, &superblock).unwrap();
+        assert_eq!(descriptor.bg_free_blocks_count, 7);
+    }
+
+    #[test]
+    fn descriptor_and_inode_round_trip() {
+        let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
+        let superblock = fs.superblock().unwrap();
+        let mut buf = vec![0; 4096];
+        fs.read_block(fs.first_descriptor_block(&superblock), &mut buf, &superblock)
+            .unwrap();
+        let descriptor = BlockGroupDescriptor::new(&buf[..32]).unwrap();
+        assert_eq!(&descriptor.to_bytes()[..], &buf[..32]);
+
+        fs.read_block(descriptor.bg_inode_table, &mut buf, &superblock)
+            .unwrap();
+        for slot in buf.chunks(128).take(superblock.s_inodes_per_group as usize) {
+            let inode = Inode::new(slot).unwrap();
+            assert_eq!(&inode.to_bytes()[..], slot);
+        }
+    }
+
+    #[test]
+    fn dir_entry_round_trip() {
+        let fs = File::open("./basic.ext2").and_then(Ext2::new).unwrap();
+        let superblock = fs.superblock().unwrap();
+        for &dir in &[2, 13] {
+            let inode = fs.get_inode(dir, &superblock).unwrap();
+            let block = inode.i_block.0[0];
+            let mut buf = vec![0; 4096];
+            fs.read_block(block, &mut buf, &superblock).unwrap();
+            let mut offset = 0;
+            while offset < buf.len() {
+                let entry = DirEntry::checked(&buf[offset..], block).unwrap();
+                let bytes = entry.to_bytes();
+                assert_eq!(bytes.len(), entry.rec_len as usize);
+                // The slack after the name may hold remnants of old entries.
+                let len = 8 + entry.name_len as usize;
+                assert_eq!(&bytes[..len], &buf[offset..offset + len]);
+                assert_eq!(DirEntry::new(&bytes), entry);
+                offset += entry.rec_len as usize;
+            }
+        }
+    }
+
+    #[test]
+    fn arbitrary_bytes_round_trip() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..1000 {
+            let raw = random_bytes(&mut state, 1024);
+            let superblock = Superblock::new(&raw).unwrap();
+            assert_eq!(&superblock.to_bytes()[..264], &raw[..264]);
+
+            let raw = random_bytes(&mut state, 32);
+            let descriptor = BlockGroupDescriptor::new(&raw).unwrap();
+            assert_eq!(&descriptor.to_bytes()[..], &raw[..]);
+
+            let raw = random_bytes(&mut state, 128);
+            let inode = Inode::new(&raw).unwrap();
+            assert_eq!(&inode.to_bytes()[..], &raw[..]);
+
+            let name_len = raw[0] as usize;
+            let entry = DirEntry {
+                inode: LE::read_u32(&raw[1..5]),
+                rec_len: ((8 + name_len + 3) & !3) as u32,
+                name_len: name_len as u8,
+                file_type: raw[5],
+                name: OsStr::from_bytes(&random_bytes(&mut state, name_len)).to_os_string(),
+            };
+            assert_eq!(DirEntry::new(&entry.to_bytes()), entry);
+        }
+    }
 }
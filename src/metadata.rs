@@ -0,0 +1,154 @@
+//! metadata.rs: Information about files, in the style of `std::fs::Metadata`.
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{ByteOrder, LE};
+
+use super::dir::not_found;
+use super::{disk, CreatorOs, Ext2, FileType, Inode, Superblock};
+
+impl<T: disk::ReadDisk> Ext2<T> {
+    /// Metadata for the file at `path`, following a final symlink.
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> io::Result<Metadata> {
+        let path = path.as_ref();
+        let sb = self.superblock()?;
+        let (ino, inode) = self.lookup(path, &sb)?.ok_or_else(|| not_found(path))?;
+        Ok(Metadata::new(ino, inode, &sb))
+    }
+
+    /// Metadata for the file at `path`, describing a final symlink itself
+    /// rather than its target.
+    pub fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> io::Result<Metadata> {
+        let path = path.as_ref();
+        let sb = self.superblock()?;
+        let (ino, inode) = self
+            .lookup_link(path, &sb)?
+            .ok_or_else(|| not_found(path))?;
+        Ok(Metadata::new(ino, inode, &sb))
+    }
+}
+
+/// Metadata about a file or directory, as returned by `Ext2::metadata`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Metadata {
+    ino: u32,
+    inode: Inode,
+    uid: u32,
+    gid: u32,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl Metadata {
+    pub(crate) fn new(ino: u32, inode: Inode, sb: &Superblock) -> Metadata {
+        // Linux keeps the high 16 bits of the owner in i_osd2.
+        let (uid_high, gid_high) = if sb.creator_os() == CreatorOs::Linux {
+            (
+                LE::read_u16(&inode.i_osd2[4..6]),
+                LE::read_u16(&inode.i_osd2[6..8]),
+            )
+        } else {
+            (0, 0)
+        };
+        Metadata {
+            ino,
+            uid: (uid_high as u32) << 16 | inode.i_uid as u32,
+            gid: (gid_high as u32) << 16 | inode.i_gid as u32,
+            inode,
+        }
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.inode.file_type()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == FileType::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type() == FileType::File
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == FileType::SymLink
+    }
+
+    /// Size of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.inode.size()
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_mode(self.inode.i_mode)
+    }
+
+    /// The full mode: file type and permission bits.
+    pub fn mode(&self) -> u16 {
+        self.inode.i_mode
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// The inode number.
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    /// Number of hard links to the file.
+    pub fn nlink(&self) -> u16 {
+        self.inode.i_links_count
+    }
+
+    /// Space used on disk, in 512-byte units, including indirect blocks.
+    pub fn blocks(&self) -> u32 {
+        self.inode.i_blocks
+    }
+
+    /// Time of the last access.
+    pub fn accessed(&self) -> SystemTime {
+        timestamp(self.inode.i_atime)
+    }
+
+    /// Time the contents last changed.
+    pub fn modified(&self) -> SystemTime {
+        timestamp(self.inode.i_mtime)
+    }
+
+    /// Time the inode last changed.  Ext2 does not record creation times.
+    pub fn changed(&self) -> SystemTime {
+        timestamp(self.inode.i_ctime)
+    }
+}
+
+/// The permission bits of a file, including the setuid, setgid and sticky
+/// bits.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Permissions(u16);
+
+impl Permissions {
+    /// Permissions from a mode, ignoring any file type bits.
+    pub fn from_mode(mode: u16) -> Permissions {
+        Permissions(mode & 0o7777)
+    }
+
+    pub fn mode(&self) -> u16 {
+        self.0
+    }
+
+    /// Whether nobody may write to the file.
+    pub fn readonly(&self) -> bool {
+        self.0 & 0o222 == 0
+    }
+}
+
+fn timestamp(secs: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs as u64)
+}
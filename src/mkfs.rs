@@ -0,0 +1,518 @@
+//! mkfs.rs: Formatting a fresh filesystem onto a disk.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+
+use uuid::Uuid;
+
+use super::alloc::Bitmap;
+use super::{
+    disk, now, BlockGroupDescriptor, DirEntry, Ext2, Ext2Error, FileType, FsPath, Inode, Superblock,
+};
+use super::{CompatFeatures, IncompatFeatures, RoCompatFeatures, EXT2_SUPER_MAGIC};
+
+/// Options for formatting a disk, in the manner of `mke2fs`.
+///
+/// ```no_run
+/// # use std::io::Cursor;
+/// let fs = ext2::Mkfs::new()
+///     .block_size(1024)
+///     .volume_label("firmware")
+///     .format(Cursor::new(vec![0; 8 << 20]), 8 << 20)
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Mkfs {
+    block_size: u32,
+    inode_size: u16,
+    inodes_per_group: Option<u32>,
+    bytes_per_inode: u32,
+    reserved_ratio: f64,
+    volume_label: String,
+    uuid: Option<Uuid>,
+    revision: u32,
+    feature_compat: CompatFeatures,
+    feature_incompat: IncompatFeatures,
+    feature_ro_compat: RoCompatFeatures,
+}
+
+impl Default for Mkfs {
+    fn default() -> Mkfs {
+        Mkfs {
+            block_size: 4096,
+            inode_size: 128,
+            inodes_per_group: None,
+            bytes_per_inode: 8192,
+            reserved_ratio: 5.0,
+            volume_label: String::new(),
+            uuid: None,
+            revision: 1,
+            feature_compat: CompatFeatures::empty(),
+            feature_incompat: IncompatFeatures::FILETYPE,
+            feature_ro_compat: RoCompatFeatures::SPARSE_SUPER | RoCompatFeatures::LARGE_FILE,
+        }
+    }
+}
+
+impl Mkfs {
+    /// Options matching a plain `mke2fs -t ext2 -b 4096 -I 128` without a
+    /// resize inode: revision 1, with the `filetype`, `sparse_super` and
+    /// `large_file` features.
+    pub fn new() -> Mkfs {
+        Mkfs::default()
+    }
+
+    /// Block size in bytes: a power of two from 1024 to 65536.
+    pub fn block_size(&mut self, block_size: u32) -> &mut Mkfs {
+        self.block_size = block_size;
+        self
+    }
+
+    /// On-disk inode size in bytes: a power of two from 128 up to the block
+    /// size.  Revision 0 filesystems always use 128.
+    pub fn inode_size(&mut self, inode_size: u16) -> &mut Mkfs {
+        self.inode_size = inode_size;
+        self
+    }
+
+    /// Inodes in each block group, rounded up to fill whole inode table
+    /// blocks.  Overrides `bytes_per_inode`.
+    pub fn inodes_per_group(&mut self, inodes_per_group: u32) -> &mut Mkfs {
+        self.inodes_per_group = Some(inodes_per_group);
+        self
+    }
+
+    /// Create one inode for every `bytes_per_inode` bytes of disk.
+    pub fn bytes_per_inode(&mut self, bytes_per_inode: u32) -> &mut Mkfs {
+        self.bytes_per_inode = bytes_per_inode;
+        self
+    }
+
+    /// Percentage of blocks reserved for the superuser, from 0 to 50.
+    pub fn reserved_ratio(&mut self, percent: f64) -> &mut Mkfs {
+        self.reserved_ratio = percent;
+        self
+    }
+
+    /// Volume label of up to 16 bytes.
+    pub fn volume_label(&mut self, label: &str) -> &mut Mkfs {
+        self.volume_label = label.to_owned();
+        self
+    }
+
+    /// Filesystem UUID.  A random one is generated if this is not set.
+    pub fn uuid(&mut self, uuid: Uuid) -> &mut Mkfs {
+        self.uuid = Some(uuid);
+        self
+    }
+
+    /// Revision 0 (the original format) or 1 (dynamic, supporting
+    /// variable inode sizes and feature flags).
+    pub fn revision(&mut self, revision: u32) -> &mut Mkfs {
+        self.revision = revision;
+        self
+    }
+
+    /// Record file types in directory entries.
+    pub fn filetype(&mut self, enable: bool) -> &mut Mkfs {
+        self.feature_incompat.set(IncompatFeatures::FILETYPE, enable);
+        self
+    }
+
+    /// Keep backup superblocks only in groups 0, 1 and powers of 3, 5 and 7.
+    pub fn sparse_super(&mut self, enable: bool) -> &mut Mkfs {
+        self.feature_ro_compat.set(RoCompatFeatures::SPARSE_SUPER, enable);
+        self
+    }
+
+    /// Allow files larger than 2GiB.
+    pub fn large_file(&mut self, enable: bool) -> &mut Mkfs {
+        self.feature_ro_compat.set(RoCompatFeatures::LARGE_FILE, enable);
+        self
+    }
+
+    /// Advertise support for extended attributes.
+    pub fn ext_attr(&mut self, enable: bool) -> &mut Mkfs {
+        self.feature_compat.set(CompatFeatures::EXT_ATTR, enable);
+        self
+    }
+
+    /// Allow hashed directory indexes.  This crate never builds them, but
+    /// other implementations may.
+    pub fn dir_index(&mut self, enable: bool) -> &mut Mkfs {
+        self.feature_compat.set(CompatFeatures::DIR_INDEX, enable);
+        self
+    }
+
+    /// Format the first `size` bytes of `disk`, returning the new filesystem.
+    ///
+    /// The filesystem contains a root directory and an empty `lost+found`.
+    pub fn format<T: disk::WriteDisk>(&self, disk: T, size: u64) -> io::Result<Ext2<T>> {
+        let mut sb = self.superblock(size)?;
+        let fs = Ext2::unchecked(disk);
+        let bs = sb.block_size();
+        let groups = sb.block_group_count();
+        let gdt_blocks = gdt_blocks(&sb);
+        let itb = inode_table_blocks(&sb);
+        let first_ino = sb.first_ino();
+
+        let zero = vec![0; bs as usize];
+        let mut descriptors = Vec::with_capacity(groups as usize);
+        for group in 0..groups {
+            let start = sb.group_first_block(group);
+            let len = sb.s_blocks_per_group.min(sb.s_blocks_count - start);
+            let bitmap_block = start + group_metadata_blocks(&sb, group, gdt_blocks);
+            let used = bitmap_block + 2 + itb - start;
+
+            // Bits past the end of the group are set, so they are never
+            // mistaken for free space.
+            let mut blocks = Bitmap::new(vec![0; bs as usize], bs * 8);
+            for bit in (0..used).chain(len..bs * 8) {
+                blocks.set(bit);
+            }
+            let mut inodes = Bitmap::new(vec![0; bs as usize], bs * 8);
+            for bit in sb.s_inodes_per_group..bs * 8 {
+                inodes.set(bit);
+            }
+            // The reserved inodes, and lost+found in the first free slot.
+            let reserved = if group == 0 { first_ino } else { 0 };
+            for bit in 0..reserved {
+                inodes.set(bit);
+            }
+            fs.write_block(bitmap_block, blocks.as_bytes(), &sb)?;
+            fs.write_block(bitmap_block + 1, inodes.as_bytes(), &sb)?;
+            for block in bitmap_block + 2..bitmap_block + 2 + itb {
+                fs.write_block(block, &zero, &sb)?;
+            }
+            descriptors.push(BlockGroupDescriptor {
+                bg_block_bitmap: bitmap_block,
+                bg_inode_bitmap: bitmap_block + 1,
+                bg_inode_table: bitmap_block + 2,
+                bg_free_blocks_count: (len - used) as u16,
+                bg_free_inodes_count: (sb.s_inodes_per_group - reserved) as u16,
+                bg_used_dirs_count: if group == 0 { 2 } else { 0 },
+                ..BlockGroupDescriptor::default()
+            });
+        }
+        sb.s_free_blocks_count = descriptors
+            .iter()
+            .map(|d| d.bg_free_blocks_count as u32)
+            .sum();
+        sb.s_free_inodes_count = sb.s_inodes_count - first_ino;
+        write_group_metadata(&fs, &sb, 0, &descriptors)?;
+        fs.refresh()?;
+
+        let time = now();
+        let lost_found = first_ino;
+        let mut root = Inode {
+            i_mode: 0x4000 | 0o755,
+            i_atime: time,
+            i_ctime: time,
+            i_mtime: time,
+            i_links_count: 3,
+            ..Inode::default()
+        };
+        write_dir_block(
+            &fs,
+            &mut root,
+            0,
+            &[(".", 2), ("..", 2), ("lost+found", lost_found)],
+            &sb,
+        )?;
+        fs.write_new_inode(2, &root, &sb)?;
+
+        // Like mke2fs, give lost+found some room up front, so that e2fsck
+        // can reconnect files without needing to allocate blocks.
+        let mut lpf = Inode {
+            i_mode: 0x4000 | 0o700,
+            i_atime: time,
+            i_ctime: time,
+            i_mtime: time,
+            i_links_count: 2,
+            ..Inode::default()
+        };
+        let lpf_blocks = (16384 / bs).clamp(2, 12);
+        write_dir_block(&fs, &mut lpf, 0, &[(".", lost_found), ("..", 2)], &sb)?;
+        for idx in 1..lpf_blocks {
+            write_dir_block(&fs, &mut lpf, idx, &[], &sb)?;
+        }
+        fs.write_new_inode(lost_found, &lpf, &sb)?;
+
+        // Copy the finished superblock and descriptors to the backup groups.
+        let sb = fs.superblock()?;
+        let descriptors = (0..groups)
+            .map(|group| fs.get_block_group_descriptor(group, &sb))
+            .collect::<io::Result<Vec<_>>>()?;
+        for group in 1..groups {
+            if has_super(&sb, group) {
+                write_group_metadata(&fs, &sb, group, &descriptors)?;
+            }
+        }
+        fs.sync()?;
+        Ok(fs)
+    }
+
+    /// Validate the options and lay out a superblock for a disk of `size`
+    /// bytes.  The free counts are left for `format` to fill in.
+    fn superblock(&self, size: u64) -> io::Result<Superblock> {
+        let bs = self.block_size;
+        if !bs.is_power_of_two() || !(1024..=65536).contains(&bs) {
+            return Err(invalid(format!("invalid block size {}", bs)));
+        }
+        let inode_size = match self.revision {
+            0 => 128,
+            1 => self.inode_size,
+            revision => return Err(invalid(format!("unknown revision {}", revision))),
+        };
+        if !inode_size.is_power_of_two() || inode_size < 128 || inode_size as u32 > bs {
+            return Err(invalid(format!("invalid inode size {}", inode_size)));
+        }
+        if self.revision == 0
+            && !(self.feature_compat.is_empty()
+                && self.feature_incompat.is_empty()
+                && self.feature_ro_compat.is_empty())
+        {
+            return Err(invalid(
+                "revision 0 does not support feature flags".to_owned(),
+            ));
+        }
+        if !(0.0..=50.0).contains(&self.reserved_ratio) {
+            return Err(invalid(format!(
+                "reserved ratio {}% is not between 0 and 50",
+                self.reserved_ratio
+            )));
+        }
+        if self.volume_label.len() > 16 {
+            return Err(invalid(format!(
+                "volume label {:?} is longer than 16 bytes",
+                self.volume_label
+            )));
+        }
+        if self.bytes_per_inode < 1024 {
+            return Err(invalid(format!(
+                "{} bytes per inode is too few",
+                self.bytes_per_inode
+            )));
+        }
+
+        let mut sb = Superblock {
+            s_blocks_count: (size / bs as u64).min(u32::MAX as u64) as u32,
+            s_first_data_block: if bs == 1024 { 1 } else { 0 },
+            s_log_block_size: bs.trailing_zeros() - 10,
+            s_log_frag_size: bs.trailing_zeros() - 10,
+            s_blocks_per_group: (bs * 8).min(MAX_PER_GROUP),
+            s_frags_per_group: (bs * 8).min(MAX_PER_GROUP),
+            s_wtime: now(),
+            s_max_mnt_count: 0xffff,
+            s_magic: EXT2_SUPER_MAGIC,
+            s_state: 1,
+            s_errors: 1,
+            s_lastcheck: now(),
+            s_rev_level: self.revision,
+            s_first_ino: if self.revision == 0 { 0 } else { 11 },
+            s_inode_size: if self.revision == 0 { 0 } else { inode_size },
+            s_feature_compat: self.feature_compat.bits(),
+            s_feature_incompat: self.feature_incompat.bits(),
+            s_feature_ro_compat: self.feature_ro_compat.bits(),
+            s_uuid: *self.uuid.unwrap_or_else(random_uuid).as_bytes(),
+            s_last_mounted: FsPath::default(),
+            ..Superblock::default()
+        };
+        sb.s_volume_name[..self.volume_label.len()].copy_from_slice(self.volume_label.as_bytes());
+        if self.feature_compat.contains(CompatFeatures::DIR_INDEX) {
+            let seed = random_uuid();
+            let seed = seed.as_bytes();
+            for (i, word) in sb.s_hash_seed.iter_mut().enumerate() {
+                *word = u32::from_le_bytes([
+                    seed[i * 4],
+                    seed[i * 4 + 1],
+                    seed[i * 4 + 2],
+                    seed[i * 4 + 3],
+                ]);
+            }
+            // half_md4
+            sb.s_def_hash_version = 1;
+        }
+
+        let inodes_per_block = bs / inode_size as u32;
+        let inode_multiple = inodes_per_block.max(8);
+        let max_inodes_per_group = MAX_PER_GROUP.min(bs * 8) / inode_multiple * inode_multiple;
+        loop {
+            let data_blocks = sb.s_blocks_count.saturating_sub(sb.s_first_data_block);
+            if data_blocks == 0 {
+                return Err(invalid("disk is too small".to_owned()));
+            }
+            let groups = data_blocks.div_ceil(sb.s_blocks_per_group);
+            let inodes_per_group = match self.inodes_per_group {
+                Some(n) => n,
+                None => {
+                    let inodes = sb.s_blocks_count as u64 * bs as u64 / self.bytes_per_inode as u64;
+                    (inodes / groups as u64) as u32
+                }
+            };
+            sb.s_inodes_per_group = inodes_per_group
+                .max(sb.first_ino() + 1)
+                .div_ceil(inode_multiple)
+                .saturating_mul(inode_multiple)
+                .min(max_inodes_per_group);
+            let inodes_count = sb.s_inodes_per_group as u64 * groups as u64;
+            if inodes_count > u32::MAX as u64 {
+                return Err(invalid("too many inodes".to_owned()));
+            }
+            sb.s_inodes_count = inodes_count as u32;
+
+            // Drop a final group too small to be worth its metadata, as
+            // mke2fs does.
+            let last = groups - 1;
+            let last_len = sb.s_blocks_count - sb.group_first_block(last);
+            let overhead =
+                group_metadata_blocks(&sb, last, gdt_blocks(&sb)) + 2 + inode_table_blocks(&sb);
+            if groups > 1 && last_len < overhead + 50 {
+                sb.s_blocks_count = sb.group_first_block(last);
+                continue;
+            }
+            // The first group must also hold the root directory and
+            // lost+found.
+            let lpf_blocks = (16384 / bs).clamp(2, 12);
+            if groups == 1 && last_len < overhead + 1 + lpf_blocks {
+                return Err(invalid("disk is too small".to_owned()));
+            }
+            break;
+        }
+        sb.s_r_blocks_count = (sb.s_blocks_count as f64 * self.reserved_ratio / 100.0) as u32;
+        Ok(sb)
+    }
+}
+
+/// The most blocks or inodes a group may have, as in mke2fs, so that the
+/// 16-bit free counts in the group descriptors cannot overflow.  Mounting
+/// rejects superblocks that exceed it.
+pub(crate) const MAX_PER_GROUP: u32 = 65528;
+
+fn invalid(msg: String) -> io::Error {
+    Ext2Error::InvalidInput(msg).into()
+}
+
+/// A random version 4 UUID.
+fn random_uuid() -> Uuid {
+    let state = RandomState::new();
+    let mut bytes = [0; 16];
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u32(now());
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Uuid::from_bytes(bytes)
+}
+
+/// Whether block group `group` holds a copy of the superblock.
+fn has_super(sb: &Superblock, group: u32) -> bool {
+    fn is_power_of(mut n: u32, base: u32) -> bool {
+        while n > 1 && n.is_multiple_of(base) {
+            n /= base;
+        }
+        n == 1
+    }
+    !sb.ro_compat_features().contains(RoCompatFeatures::SPARSE_SUPER)
+        || group <= 1
+        || is_power_of(group, 3)
+        || is_power_of(group, 5)
+        || is_power_of(group, 7)
+}
+
+fn gdt_blocks(sb: &Superblock) -> u32 {
+    (sb.block_group_count() * 32).div_ceil(sb.block_size())
+}
+
+fn inode_table_blocks(sb: &Superblock) -> u32 {
+    (sb.s_inodes_per_group * sb.inode_size()).div_ceil(sb.block_size())
+}
+
+/// Blocks taken by the superblock and descriptor table copies at the start
+/// of a group.
+fn group_metadata_blocks(sb: &Superblock, group: u32, gdt_blocks: u32) -> u32 {
+    if has_super(sb, group) {
+        1 + gdt_blocks
+    } else {
+        0
+    }
+}
+
+/// Write a copy of the superblock and the block group descriptor table at
+/// the start of group `group`.
+fn write_group_metadata<T: disk::WriteDisk>(
+    fs: &Ext2<T>,
+    sb: &Superblock,
+    group: u32,
+    descriptors: &[BlockGroupDescriptor],
+) -> io::Result<()> {
+    let bs = sb.block_size() as usize;
+    let start = sb.group_first_block(group);
+    // The primary superblock always lives 1024 bytes into the disk, which
+    // is partway into block 0 for larger block sizes.
+    let offset = if group == 0 && bs > 1024 { 1024 } else { 0 };
+    let mut buf = vec![0; bs];
+    let copy = Superblock {
+        s_block_group_nr: group as u16,
+        ..sb.clone()
+    };
+    copy.write_to(&mut buf[offset..offset + 1024]);
+    fs.write_block(start, &buf, sb)?;
+
+    let mut table = vec![0; gdt_blocks(sb) as usize * bs];
+    for (descriptor, chunk) in descriptors.iter().zip(table.chunks_mut(32)) {
+        descriptor.write_to(chunk);
+    }
+    for (i, block) in table.chunks(bs).enumerate() {
+        fs.write_block(start + 1 + i as u32, block, sb)?;
+    }
+    Ok(())
+}
+
+/// Allocate block `idx` of directory `inode` and fill it with `entries`.
+/// The directory's size is extended to cover the block.
+fn write_dir_block<T: disk::WriteDisk>(
+    fs: &Ext2<T>,
+    inode: &mut Inode,
+    idx: u32,
+    entries: &[(&str, u32)],
+    sb: &Superblock,
+) -> io::Result<()> {
+    let bs = sb.block_size() as usize;
+    let (block, _) = fs.map_block(inode, idx, sb.group_first_block(0), sb)?;
+    let mut buf = vec![0; bs];
+    let mut offset = 0;
+    // An empty block holds a single unused record spanning all of it.
+    let entries = if entries.is_empty() {
+        &[("", 0)][..]
+    } else {
+        entries
+    };
+    for (i, &(name, inum)) in entries.iter().enumerate() {
+        let mut entry = DirEntry {
+            inode: inum,
+            rec_len: 0,
+            name_len: name.len() as u8,
+            file_type: if inum != 0 && sb.incompat_features().contains(IncompatFeatures::FILETYPE) {
+                FileType::Directory as u8
+            } else {
+                0
+            },
+            name: name.into(),
+        };
+        entry.rec_len = if i + 1 == entries.len() {
+            (bs - offset) as u32
+        } else {
+            entry.min_rec_len() as u32
+        };
+        entry.write_to(&mut buf[offset..]);
+        offset += entry.rec_len as usize;
+    }
+    fs.write_block(block, &buf, sb)?;
+    inode.set_size((idx as u64 + 1) * bs as u64);
+    Ok(())
+}
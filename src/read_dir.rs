@@ -0,0 +1,191 @@
+//! read_dir.rs: Listing directories, in the style of `std::fs::read_dir`.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{
+    disk, DirEntry, Ext2, Ext2Error, FileType, IncompatFeatures, Inode, Metadata, Superblock,
+};
+
+impl<T: disk::ReadDisk> Ext2<T> {
+    /// Iterate over the entries of the directory at `path`.
+    ///
+    /// Entries are read from disk a block at a time as the iterator
+    /// advances.  The `.` and `..` entries are skipped.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<ReadDir<'_, T>> {
+        let path = path.as_ref();
+        let sb = self.superblock()?;
+        let (_, inode) = self
+            .lookup(path, &sb)?
+            .ok_or_else(|| Ext2Error::NotFound(path.to_owned()))?;
+        if inode.file_type() != FileType::Directory {
+            return Err(Ext2Error::NotADirectory(path.to_owned()).into());
+        }
+        ReadDir::new(self, path, sb, inode)
+    }
+}
+
+/// Iterator over the entries of a directory, returned by `Ext2::read_dir`.
+pub struct ReadDir<'fs, T: disk::ReadDisk + 'fs> {
+    fs: &'fs Ext2<T>,
+    sb: Superblock,
+    path: PathBuf,
+    inode: Inode,
+    /// Logical index of the next directory block to read.
+    next_block: u32,
+    blocks: u32,
+    /// The block in `buf`.
+    ptr: u32,
+    buf: Vec<u8>,
+    /// Offset of the next entry in `buf`.
+    offset: usize,
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> ReadDir<'fs, T> {
+    pub(crate) fn new(
+        fs: &'fs Ext2<T>,
+        path: &Path,
+        sb: Superblock,
+        inode: Inode,
+    ) -> io::Result<ReadDir<'fs, T>> {
+        let bs = sb.block_size() as usize;
+        Ok(ReadDir {
+            fs,
+            blocks: fs.dir_block_count(&inode, &sb)?,
+            sb,
+            path: path.to_owned(),
+            inode,
+            next_block: 0,
+            ptr: 0,
+            buf: vec![0; bs],
+            offset: bs,
+        })
+    }
+
+    /// Load the next block of the directory into `buf`.  Returns false
+    /// when there are no more blocks.
+    fn next_block(&mut self) -> io::Result<bool> {
+        while self.next_block < self.blocks {
+            let idx = self.next_block;
+            self.next_block += 1;
+            match self.fs.get_block_ptr(&self.inode, idx, &self.sb)? {
+                0 => continue,
+                ptr => {
+                    self.fs.read_block(ptr, &mut self.buf, &self.sb)?;
+                    self.ptr = ptr;
+                    self.offset = 0;
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// The next entry in use, including `.` and `..`.
+    fn next_entry(&mut self) -> io::Result<Option<DirEntry>> {
+        loop {
+            if self.offset >= self.buf.len() && !self.next_block()? {
+                return Ok(None);
+            }
+            let entry = DirEntry::checked(&self.buf[self.offset..], self.ptr)?;
+            self.offset += entry.rec_len as usize;
+            if entry.inode != 0 {
+                return Ok(Some(entry));
+            }
+        }
+    }
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> Iterator for ReadDir<'fs, T> {
+    type Item = io::Result<Entry<'fs, T>>;
+
+    fn next(&mut self) -> Option<io::Result<Entry<'fs, T>>> {
+        loop {
+            match self.next_entry() {
+                Ok(Some(entry)) => {
+                    if entry.name == "." || entry.name == ".." {
+                        continue;
+                    }
+                    let has_file_type = self
+                        .sb
+                        .incompat_features()
+                        .contains(IncompatFeatures::FILETYPE);
+                    return Some(Ok(Entry {
+                        fs: self.fs,
+                        path: self.path.join(&entry.name),
+                        ino: entry.inode,
+                        file_type: if has_file_type { entry.file_type } else { 0 },
+                        name: entry.name,
+                    }));
+                }
+                Ok(None) => return None,
+                Err(err) => {
+                    // Give up on the directory rather than yielding the same
+                    // error forever.
+                    self.next_block = self.blocks;
+                    self.offset = self.buf.len();
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// An entry in a directory, yielded by `ReadDir`.
+pub struct Entry<'fs, T: disk::ReadDisk + 'fs> {
+    fs: &'fs Ext2<T>,
+    path: PathBuf,
+    name: OsString,
+    ino: u32,
+    /// The file type recorded in the directory entry, or 0 if the
+    /// filesystem does not record them.
+    file_type: u8,
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> Entry<'fs, T> {
+    /// The full path of the entry: the directory path joined with its name.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// The inode number the entry links to.
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    /// The type of the entry.  This usually comes from the directory entry
+    /// itself, but falls back to reading the inode on filesystems without
+    /// the `filetype` feature.
+    pub fn file_type(&self) -> io::Result<FileType> {
+        match dirent_file_type(self.file_type) {
+            Some(file_type) => Ok(file_type),
+            None => self.metadata().map(|metadata| metadata.file_type()),
+        }
+    }
+
+    /// Read the entry's inode.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        let sb = self.fs.superblock()?;
+        let inode = self.fs.get_inode(self.ino, &sb)?;
+        Ok(Metadata::new(self.ino, inode, &sb))
+    }
+}
+
+fn dirent_file_type(file_type: u8) -> Option<FileType> {
+    use FileType::*;
+    match file_type {
+        1 => Some(File),
+        2 => Some(Directory),
+        3 => Some(CharDev),
+        4 => Some(BlockDev),
+        5 => Some(FIFO),
+        6 => Some(UnixSocket),
+        7 => Some(SymLink),
+        _ => None,
+    }
+}
@@ -0,0 +1,121 @@
+//! symlink.rs: Reading and creating symbolic links.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, LE};
+
+use super::dir::{already_exists, not_found};
+use super::{alloc, disk, now, Ext2, Ext2Error, FileType, Inode, Superblock};
+
+/// Targets shorter than this are kept in the inode's block pointers.
+const FAST_SYMLINK_MAX: usize = 60;
+
+impl<T: disk::ReadDisk> Ext2<T> {
+    /// The target of the symlink at `path`.
+    pub fn read_link<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+        let path = path.as_ref();
+        let sb = self.superblock()?;
+        let (_, inode) = self
+            .lookup_link(path, &sb)?
+            .ok_or_else(|| not_found(path))?;
+        if inode.file_type() != FileType::SymLink {
+            return Err(Ext2Error::InvalidInput(format!("{:?} is not a symlink", path)).into());
+        }
+        self.read_link_target(&inode, &sb).map(PathBuf::from)
+    }
+
+    /// The target of symlink `inode`.
+    pub(crate) fn read_link_target(&self, inode: &Inode, sb: &Superblock) -> io::Result<OsString> {
+        let len = inode.size() as usize;
+        if inode.is_fast_symlink(sb) {
+            let buf = inode.to_bytes();
+            let len = len.min(FAST_SYMLINK_MAX);
+            return Ok(OsStr::from_bytes(&buf[40..40 + len]).to_os_string());
+        }
+        let mut buf = vec![0; sb.block_size() as usize];
+        match self.get_block_ptr(inode, 0, sb)? {
+            0 => Ok(OsString::new()),
+            ptr => {
+                self.read_block(ptr, &mut buf, sb)?;
+                let len = len.min(buf.len());
+                Ok(OsStr::from_bytes(&buf[..len]).to_os_string())
+            }
+        }
+    }
+}
+
+impl<T: disk::WriteDisk> Ext2<T> {
+    /// Create a symlink at `link` pointing to `target`, which need not
+    /// exist.  Relative targets are resolved from the directory containing
+    /// the link.
+    pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, target: P, link: Q) -> io::Result<()> {
+        let (target, link) = (target.as_ref().as_os_str().as_bytes(), link.as_ref());
+        let sb = self.superblock()?;
+        let bs = sb.block_size() as usize;
+        if target.is_empty() || target.len() >= bs {
+            return Err(Ext2Error::InvalidInput(format!(
+                "symlink target must be between 1 and {} bytes",
+                bs - 1
+            ))
+            .into());
+        }
+        let (parent, mut parent_inode, name) = self.parent_dir(link, &sb)?;
+        if self.get_inum_in_dir(&parent_inode, name, &sb)?.is_some() {
+            return Err(already_exists(link));
+        }
+        let inum = alloc::Allocator::new(self, &sb).alloc_inode(parent, false)?;
+        let time = now();
+        let mut inode = Inode {
+            i_mode: 0xa000 | 0o777,
+            i_atime: time,
+            i_ctime: time,
+            i_mtime: time,
+            i_links_count: 1,
+            ..Inode::default()
+        };
+        self.store_symlink_target(inum, &mut inode, target, &sb)
+            .and_then(|()| {
+                self.add_dir_entry(
+                    &mut parent_inode,
+                    parent,
+                    name,
+                    inum,
+                    FileType::SymLink,
+                    &sb,
+                )
+            })
+            .map_err(|err| self.abandon_inode(inum, &mut inode, &sb, err))
+    }
+
+    /// Store `target` in the new symlink `inum`, in `i_block` if it fits and
+    /// in a block of its own if not, and write its inode.
+    fn store_symlink_target(
+        &self,
+        inum: u32,
+        inode: &mut Inode,
+        target: &[u8],
+        sb: &Superblock,
+    ) -> io::Result<()> {
+        if target.len() < FAST_SYMLINK_MAX {
+            let mut bytes = [0; FAST_SYMLINK_MAX];
+            bytes[..target.len()].copy_from_slice(target);
+            for (ptr, chunk) in inode.i_block.0.iter_mut().zip(bytes.chunks(4)) {
+                *ptr = LE::read_u32(chunk);
+            }
+            inode.i_block.1 = LE::read_u32(&bytes[48..52]);
+            inode.i_block.2 = LE::read_u32(&bytes[52..56]);
+            inode.i_block.3 = LE::read_u32(&bytes[56..60]);
+        } else {
+            let goal = sb.group_first_block(sb.locate_inode(inum).0);
+            let (block, _) = self.map_block(inode, 0, goal, sb)?;
+            let mut buf = vec![0; sb.block_size() as usize];
+            buf[..target.len()].copy_from_slice(target);
+            self.write_block(block, &buf, sb)?;
+        }
+        inode.set_size(target.len() as u64);
+        self.write_new_inode(inum, inode, sb)
+    }
+}
@@ -0,0 +1,283 @@
+//! walk.rs: Recursively walking a directory tree, in the style of the
+//! walkdir crate.
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{disk, Ext2, Ext2Error, FileType, Metadata, ReadDir, Superblock};
+
+impl<T: disk::ReadDisk> Ext2<T> {
+    /// Walk the tree rooted at `path`, yielding `path` itself and then
+    /// everything below it.
+    ///
+    /// ```no_run
+    /// # use std::fs::File;
+    /// # let fs = File::open("basic.ext2").and_then(ext2::Ext2::new).unwrap();
+    /// for entry in fs.walk("/").max_depth(2).file_types(&[ext2::FileType::File]) {
+    ///     println!("{}", entry.unwrap().path().display());
+    /// }
+    /// ```
+    pub fn walk<P: AsRef<Path>>(&self, path: P) -> Walk<'_, T> {
+        Walk {
+            fs: self,
+            root: Some(path.as_ref().to_owned()),
+            sb: None,
+            min_depth: 0,
+            max_depth: usize::MAX,
+            contents_first: false,
+            follow_links: false,
+            file_types: None,
+            stack: Vec::new(),
+        }
+    }
+}
+
+/// Iterator over a directory tree, returned by `Ext2::walk`.
+///
+/// Unlike the other builders in this crate, the options take and return the
+/// walker by value, so that they can be chained straight into a `for` loop.
+pub struct Walk<'fs, T: disk::ReadDisk + 'fs> {
+    fs: &'fs Ext2<T>,
+    /// The starting path, until the first call to `next`.
+    root: Option<PathBuf>,
+    sb: Option<Superblock>,
+    min_depth: usize,
+    max_depth: usize,
+    contents_first: bool,
+    follow_links: bool,
+    file_types: Option<Vec<FileType>>,
+    /// The directories currently being read, outermost first.
+    stack: Vec<Level<'fs, T>>,
+}
+
+struct Level<'fs, T: disk::ReadDisk + 'fs> {
+    read_dir: ReadDir<'fs, T>,
+    ino: u32,
+    /// The directory itself, held back until its contents have been
+    /// yielded when walking contents first.
+    dir: Option<WalkEntry<'fs, T>>,
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> Walk<'fs, T> {
+    /// Skip entries shallower than `depth`.  The starting path is at depth 0
+    /// and its children at depth 1.
+    pub fn min_depth(mut self, depth: usize) -> Walk<'fs, T> {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Do not descend below `depth`.
+    pub fn max_depth(mut self, depth: usize) -> Walk<'fs, T> {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Yield the contents of each directory before the directory itself
+    /// (post-order), rather than after it (pre-order, the default).
+    pub fn contents_first(mut self, contents_first: bool) -> Walk<'fs, T> {
+        self.contents_first = contents_first;
+        self
+    }
+
+    /// Follow symlinks, walking into linked directories and reporting the
+    /// type of the target rather than of the link.  Off by default.
+    ///
+    /// Links back to a directory being walked yield an error rather than
+    /// looping forever.
+    pub fn follow_links(mut self, follow_links: bool) -> Walk<'fs, T> {
+        self.follow_links = follow_links;
+        self
+    }
+
+    /// Only yield entries of these types.  Directories are still walked
+    /// into whether or not they are yielded.
+    pub fn file_types(mut self, file_types: &[FileType]) -> Walk<'fs, T> {
+        self.file_types = Some(file_types.to_vec());
+        self
+    }
+
+    fn start(&mut self, root: PathBuf) -> io::Result<WalkEntry<'fs, T>> {
+        let sb = self.fs.superblock()?;
+        let (ino, inode) = self
+            .fs
+            .lookup_link(&root, &sb)?
+            .ok_or_else(|| Ext2Error::NotFound(root.to_owned()))?;
+        let entry = self.entry(root, 0, ino, inode.file_type(), &sb)?;
+        self.sb = Some(sb);
+        Ok(entry)
+    }
+
+    /// Build an entry, following it if it is a symlink and we were asked to.
+    fn entry(
+        &self,
+        path: PathBuf,
+        depth: usize,
+        ino: u32,
+        file_type: FileType,
+        sb: &Superblock,
+    ) -> io::Result<WalkEntry<'fs, T>> {
+        let mut entry = WalkEntry {
+            fs: self.fs,
+            path,
+            depth,
+            ino,
+            file_type,
+            followed_link: false,
+        };
+        if self.follow_links && file_type == FileType::SymLink {
+            let (ino, inode) = self
+                .fs
+                .lookup(&entry.path, sb)?
+                .ok_or_else(|| Ext2Error::NotFound(entry.path.clone()))?;
+            entry.ino = ino;
+            entry.file_type = inode.file_type();
+            entry.followed_link = true;
+        }
+        Ok(entry)
+    }
+
+    /// Start reading `entry` if it is a directory we should walk into, then
+    /// return it if it should be yielded now.
+    fn visit(&mut self, entry: WalkEntry<'fs, T>) -> Option<io::Result<WalkEntry<'fs, T>>> {
+        if entry.file_type == FileType::Directory && entry.depth < self.max_depth {
+            if self.stack.iter().any(|level| level.ino == entry.ino) {
+                return Some(Err(Ext2Error::SymlinkLoop(entry.path).into()));
+            }
+            let sb = self.sb.clone().unwrap();
+            let inode = match self.fs.get_inode(entry.ino, &sb) {
+                Ok(inode) => inode,
+                Err(err) => return Some(Err(err)),
+            };
+            let read_dir = match ReadDir::new(self.fs, &entry.path, sb, inode) {
+                Ok(read_dir) => read_dir,
+                Err(err) => return Some(Err(err)),
+            };
+            let ino = entry.ino;
+            if self.contents_first {
+                self.stack.push(Level {
+                    read_dir,
+                    ino,
+                    dir: Some(entry),
+                });
+                return None;
+            }
+            self.stack.push(Level {
+                read_dir,
+                ino,
+                dir: None,
+            });
+        }
+        self.yield_entry(entry)
+    }
+
+    fn yield_entry(&self, entry: WalkEntry<'fs, T>) -> Option<io::Result<WalkEntry<'fs, T>>> {
+        let wanted = match self.file_types {
+            Some(ref file_types) => file_types.contains(&entry.file_type),
+            None => true,
+        };
+        if entry.depth >= self.min_depth && wanted {
+            Some(Ok(entry))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> Iterator for Walk<'fs, T> {
+    type Item = io::Result<WalkEntry<'fs, T>>;
+
+    fn next(&mut self) -> Option<io::Result<WalkEntry<'fs, T>>> {
+        if let Some(root) = self.root.take() {
+            match self.start(root) {
+                Ok(entry) => {
+                    if let Some(result) = self.visit(entry) {
+                        return Some(result);
+                    }
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        loop {
+            let depth = self.stack.len();
+            let next = self.stack.last_mut()?.read_dir.next();
+            let result = match next {
+                Some(Ok(entry)) => {
+                    let file_type = match entry.file_type() {
+                        Ok(file_type) => file_type,
+                        Err(err) => return Some(Err(err)),
+                    };
+                    let sb = self.sb.as_ref().unwrap();
+                    match self.entry(entry.path(), depth, entry.ino(), file_type, sb) {
+                        Ok(entry) => self.visit(entry),
+                        Err(err) => Some(Err(err)),
+                    }
+                }
+                Some(Err(err)) => Some(Err(err)),
+                None => {
+                    let level = self.stack.pop().unwrap();
+                    level.dir.and_then(|dir| self.yield_entry(dir))
+                }
+            };
+            if result.is_some() {
+                return result;
+            }
+        }
+    }
+}
+
+/// An entry yielded by `Walk`.
+pub struct WalkEntry<'fs, T: disk::ReadDisk + 'fs> {
+    fs: &'fs Ext2<T>,
+    path: PathBuf,
+    depth: usize,
+    ino: u32,
+    file_type: FileType,
+    followed_link: bool,
+}
+
+impl<'fs, T: disk::ReadDisk + 'fs> WalkEntry<'fs, T> {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// The last component of the path, or the whole path for `/`.
+    pub fn file_name(&self) -> &OsStr {
+        self.path
+            .file_name()
+            .unwrap_or_else(|| self.path.as_os_str())
+    }
+
+    /// How far below the starting path the entry is.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The inode number, of the link's target if the entry is a followed
+    /// symlink.
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    /// The file type, of the link's target if the entry is a followed
+    /// symlink.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Whether the entry is a symlink that was followed.
+    pub fn path_is_symlink(&self) -> bool {
+        self.followed_link || self.file_type == FileType::SymLink
+    }
+
+    /// Read the entry's inode, following symlinks like `file_type`.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        let sb = self.fs.superblock()?;
+        let inode = self.fs.get_inode(self.ino, &sb)?;
+        Ok(Metadata::new(self.ino, inode, &sb))
+    }
+}
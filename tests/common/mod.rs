@@ -0,0 +1,89 @@
+//! Helpers shared by the integration tests.
+
+// Each test binary only uses some of these.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::{self, Cursor};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ext2::{Ext2, Mkfs};
+
+/// An in-memory copy of basic.ext2 that tests are free to modify.
+pub fn scratch() -> Ext2<Cursor<Vec<u8>>> {
+    let image = fs::read("basic.ext2").unwrap();
+    Ext2::new(Cursor::new(image)).unwrap()
+}
+
+/// A freshly formatted in-memory image of `size` bytes.
+pub fn formatted(options: &Mkfs, size: usize) -> Ext2<Cursor<Vec<u8>>> {
+    options
+        .format(Cursor::new(vec![0; size]), size as u64)
+        .unwrap()
+}
+
+/// Run `e2fsck -fn` over the image, skipping the check if e2fsprogs is not
+/// installed.
+pub fn fsck(fs: Ext2<Cursor<Vec<u8>>>, name: &str) {
+    let path = env::temp_dir().join(format!("ext2-{}-{}.img", name, std::process::id()));
+    fs::write(&path, fs.into_inner().into_inner()).unwrap();
+    let output = Command::new("e2fsck").arg("-fn").arg(&path).output();
+    fs::remove_file(&path).unwrap();
+    match output {
+        Ok(output) => assert!(
+            output.status.success(),
+            "e2fsck reported problems:\n{}",
+            String::from_utf8_lossy(&output.stdout)
+        ),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            eprintln!("e2fsck not installed; skipping consistency check");
+        }
+        Err(err) => panic!("could not run e2fsck: {}", err),
+    }
+}
+
+/// Run the `debugfs` `commands` over a copy of basic.ext2, for building
+/// files this crate cannot make, such as device nodes.  Returns `None` if
+/// e2fsprogs is not installed.
+pub fn debugfs(commands: &[&str]) -> Option<Ext2<Cursor<Vec<u8>>>> {
+    // Tests run on parallel threads, so each call needs an image of its own.
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!(
+        "ext2-debugfs-{}-{}.img",
+        std::process::id(),
+        CALLS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::copy("basic.ext2", &path).unwrap();
+    let mut result = Ok(());
+    let mut failed = None;
+    for command in commands {
+        let output = Command::new("debugfs")
+            .arg("-w")
+            .arg("-R")
+            .arg(command)
+            .arg(&path)
+            .output();
+        match output {
+            Ok(ref output) if output.status.success() => continue,
+            Ok(_) => failed = Some(command),
+            Err(err) => result = Err(err),
+        }
+        break;
+    }
+    // Clean up before any panic, so that a failed test leaves nothing behind.
+    let image = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    if let Some(command) = failed {
+        panic!("debugfs {:?} failed", command);
+    }
+    match result {
+        Ok(()) => Some(Ext2::new(Cursor::new(image)).unwrap()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            eprintln!("debugfs not installed; skipping");
+            None
+        }
+        Err(err) => panic!("could not run debugfs: {}", err),
+    }
+}
@@ -1,11 +1,14 @@
 #![cfg(test)]
+// `file_seek` means to test `SeekFrom::Current(0)` rather than
+// `stream_position`.
+#![allow(clippy::seek_from_current)]
 
 extern crate ext2;
 
 use std::fs::File;
 use std::io::{self, Read, Seek};
 
-use ext2::Ext2;
+use ext2::{BlockCache, CacheStats, Ext2, OpenOptions};
 
 #[test]
 fn file_open() {
@@ -40,13 +43,19 @@ fn file_read_full_block() {
 
 #[test]
 fn file_read_more_than_a_block() {
-    // Current implementation only reads one block at a time.
     let fs = File::open("basic.ext2").and_then(Ext2::new).unwrap();
     let mut f = fs.open("/sub/michelle.jpg").unwrap();
     let mut buf = [255; 4099];
     let x = f.read(&mut buf[..]).unwrap();
-    assert_eq!(x, 4096);
-    assert_eq!(&buf[4094..], b"\x66\x47\xff\xff\xff");
+    assert_eq!(x, 4099);
+    assert_eq!(&buf[4094..], b"\x66\x47\x64\xf5\x8f");
+
+    // A read fills the whole buffer, through the indirect blocks, up to
+    // the end of the file.
+    f.seek(io::SeekFrom::Start(100)).unwrap();
+    let mut buf = vec![0; 80000];
+    assert_eq!(f.read(&mut buf).unwrap(), 75457);
+    assert_eq!(&buf[75451..75457], b"\xd4\x67\x2f\x4f\xff\xd9");
 }
 
 #[test]
@@ -94,3 +103,71 @@ fn read_large_file() {
         );
     }
 }
+
+#[test]
+fn small_reads_are_served_from_read_ahead() {
+    let disk = BlockCache::new(File::open("basic.ext2").unwrap(), 64);
+    let fs = Ext2::new(disk).unwrap();
+    let mut f = OpenOptions::new()
+        .read(true)
+        .read_ahead(16)
+        .open(&fs, "/sub/pattern/test_pattern.txt")
+        .unwrap();
+    let mut contents = vec![0; 100];
+    f.read_exact(&mut contents).unwrap();
+    fs.with_disk(|cache| cache.reset_stats());
+
+    let mut buf = [0; 100];
+    loop {
+        match f.read(&mut buf).unwrap() {
+            0 => break,
+            n => contents.extend_from_slice(&buf[..n]),
+        }
+    }
+    assert_eq!(fs.with_disk(|cache| cache.stats()), CacheStats::default());
+    assert_eq!(contents, std::fs::read("data/test_pattern.txt").unwrap());
+}
+
+#[test]
+fn unaligned_reads_span_whole_blocks() {
+    let fs = Ext2::new(File::open("basic.ext2").unwrap()).unwrap();
+    let expected = std::fs::read("data/test_pattern.txt").unwrap();
+    for &read_ahead in &[0, 4] {
+        let mut f = OpenOptions::new()
+            .read(true)
+            .read_ahead(read_ahead)
+            .open(&fs, "/sub/pattern/test_pattern.txt")
+            .unwrap();
+        // A partial block at either end, or both, and whole ones between.
+        for &(offset, len) in &[(1, 3 * 4096), (4095, 2 * 4096 + 2), (0, 4096 + 7)] {
+            f.seek(io::SeekFrom::Start(offset as u64)).unwrap();
+            let mut buf = vec![0; len];
+            f.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, &expected[offset..offset + len]);
+        }
+    }
+}
+
+#[test]
+fn read_at_leaves_position_alone() {
+    let fs = Ext2::new(File::open("basic.ext2").unwrap()).unwrap();
+    let mut f = fs.open("/sub/pattern/test_pattern.txt").unwrap();
+    let expected = std::fs::read("data/test_pattern.txt").unwrap();
+    f.seek(io::SeekFrom::Start(10)).unwrap();
+
+    // Within a block, across blocks, and whole blocks.
+    for &(offset, len) in &[(5, 100), (4000, 200), (8192, 8192), (4095, 4098)] {
+        let mut buf = vec![0; len];
+        f.read_exact_at(&mut buf, offset as u64).unwrap();
+        assert_eq!(buf, &expected[offset..offset + len]);
+    }
+    let mut buf = [0; 100];
+    assert_eq!(f.read_at(&mut buf, 65500).unwrap(), 36);
+    assert_eq!(f.read_at(&mut buf, 70000).unwrap(), 0);
+    let err = f.read_exact_at(&mut buf, 65500).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    assert_eq!(f.stream_position().unwrap(), 10);
+    f.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &expected[10..110]);
+}
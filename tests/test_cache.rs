@@ -0,0 +1,154 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use ext2::{BlockCache, Ext2};
+
+mod common;
+use common::fsck;
+
+/// An in-memory disk that records which sectors are read.
+struct Recording {
+    inner: Cursor<Vec<u8>>,
+    reads: Rc<RefCell<Vec<u64>>>,
+}
+
+impl Read for Recording {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads.borrow_mut().push(self.inner.position() / 512);
+        self.inner.read(buf)
+    }
+}
+
+impl Write for Recording {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Recording {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+fn mount() -> (Ext2<Recording>, Rc<RefCell<Vec<u64>>>) {
+    let reads = Rc::new(RefCell::new(Vec::new()));
+    let disk = Recording {
+        inner: Cursor::new(fs::read("basic.ext2").unwrap()),
+        reads: reads.clone(),
+    };
+    (Ext2::new(disk).unwrap(), reads)
+}
+
+/// Sectors holding the superblock (1024 bytes in) and the descriptor
+/// table (block 1) of basic.ext2.
+fn is_metadata(sector: u64) -> bool {
+    (2..4).contains(&sector) || (8..16).contains(&sector)
+}
+
+#[test]
+fn mounting_reads_metadata_once() {
+    let (_fs, reads) = mount();
+    let reads = reads.borrow();
+    assert_eq!(reads.iter().filter(|&&sector| sector == 2).count(), 1);
+    assert_eq!(reads.iter().filter(|&&sector| sector == 8).count(), 1);
+}
+
+#[test]
+fn lookups_do_not_reread_metadata() {
+    let (fs, reads) = mount();
+    reads.borrow_mut().clear();
+    for _ in 0..3 {
+        fs.open("/sub/pattern/test_pattern.txt").unwrap();
+        fs.metadata("/hello.txt").unwrap();
+        fs.read_dir("/sub").unwrap().count();
+    }
+    let reads = reads.borrow();
+    assert!(!reads.is_empty());
+    assert!(
+        !reads.iter().any(|&sector| is_metadata(sector)),
+        "metadata re-read: {:?}",
+        reads
+    );
+}
+
+#[test]
+fn writes_keep_cache_in_step() {
+    let (fs, reads) = mount();
+    fs.create_dir("/new").unwrap();
+    fs.create("/new/file").unwrap().write_all(b"data").unwrap();
+    reads.borrow_mut().clear();
+    assert!(fs.metadata("/new/file").unwrap().is_file());
+    assert!(!reads.borrow().iter().any(|&sector| is_metadata(sector)));
+
+    // The free counts on disk agree with the bitmaps.
+    let image = fs.into_inner().inner.into_inner();
+    fsck(Ext2::new(Cursor::new(image)).unwrap(), "cache");
+}
+
+#[test]
+fn block_cache_serves_repeated_reads() {
+    let disk = BlockCache::new(Cursor::new(fs::read("basic.ext2").unwrap()), 64);
+    let fs = Ext2::new(disk).unwrap();
+    let mut first = String::new();
+    fs.open("/sub/pattern/test_pattern.txt")
+        .unwrap()
+        .read_to_string(&mut first)
+        .unwrap();
+    fs.with_disk(|cache| cache.reset_stats());
+
+    let mut second = String::new();
+    fs.open("/sub/pattern/test_pattern.txt")
+        .unwrap()
+        .read_to_string(&mut second)
+        .unwrap();
+    assert_eq!(first, second);
+    let stats = fs.with_disk(|cache| cache.stats());
+    assert!(stats.hits > 0);
+    assert_eq!(stats.misses, 0);
+}
+
+#[test]
+fn block_cache_writes_back_on_sync() {
+    let disk = BlockCache::new(Cursor::new(fs::read("basic.ext2").unwrap()), 64);
+    let fs = Ext2::new(disk).unwrap();
+    fs.create_dir("/new").unwrap();
+    fs.create("/new/file").unwrap().write_all(b"data").unwrap();
+    assert_eq!(fs.with_disk(|cache| cache.stats().writebacks), 0);
+
+    fs.sync().unwrap();
+    assert!(fs.with_disk(|cache| cache.stats().writebacks) > 0);
+    let image = fs.into_inner().into_inner().unwrap().into_inner();
+    fsck(Ext2::new(Cursor::new(image)).unwrap(), "block_cache");
+}
+
+#[test]
+fn small_block_cache_evicts_dirty_blocks() {
+    let disk = BlockCache::new(Cursor::new(fs::read("basic.ext2").unwrap()), 2);
+    let fs = Ext2::new(disk).unwrap();
+    fs.create_dir("/new").unwrap();
+    for i in 0..4 {
+        let path = format!("/new/{}", i);
+        fs.create(&path).unwrap().write_all(&[i; 4100]).unwrap();
+    }
+    assert!(fs.with_disk(|cache| cache.stats().evictions) > 0);
+    let mut contents = Vec::new();
+    fs.open("/new/3")
+        .unwrap()
+        .read_to_end(&mut contents)
+        .unwrap();
+    assert_eq!(contents, vec![3; 4100]);
+
+    let image = fs.into_inner().into_inner().unwrap().into_inner();
+    fsck(Ext2::new(Cursor::new(image)).unwrap(), "small_block_cache");
+}
@@ -0,0 +1,170 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::env;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::sync::Arc;
+use std::thread;
+
+use ext2::{Ext2, Mkfs, OpenOptions, OwnedHandle, ReadAtDisk};
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+fn assert_send_static<T: Send + 'static>() {}
+
+#[test]
+fn shared_between_threads() {
+    assert_send_sync::<Ext2<ReadAtDisk<fs::File>>>();
+
+    let image = Arc::new(fs::read("basic.ext2").unwrap());
+    let fs = Arc::new(Ext2::new(ReadAtDisk::new(image)).unwrap());
+    let expected = fs::read("data/test_pattern.txt").unwrap();
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let fs = fs.clone();
+            thread::spawn(move || {
+                let mut contents = Vec::new();
+                for _ in 0..20 {
+                    contents.clear();
+                    fs.open("/sub/pattern/test_pattern.txt")
+                        .unwrap()
+                        .read_to_end(&mut contents)
+                        .unwrap();
+                }
+                contents
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), expected);
+    }
+}
+
+#[test]
+fn file_on_4k_sectors() {
+    let file = fs::File::open("basic.ext2").unwrap();
+    let fs = Ext2::new(ReadAtDisk::with_sector_size(file, 4096)).unwrap();
+    let mut contents = String::new();
+    fs.open("/hello.txt")
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "Hello world!\n");
+}
+
+/// Open a file on a filesystem that only the handle keeps alive.
+fn open_pattern() -> OwnedHandle<ReadAtDisk<Vec<u8>>> {
+    let image = fs::read("basic.ext2").unwrap();
+    let fs = Arc::new(Ext2::new(ReadAtDisk::new(image)).unwrap());
+    fs.open_owned("/sub/pattern/test_pattern.txt").unwrap()
+}
+
+#[test]
+fn owned_handles_outlive_the_filesystem() {
+    assert_send_static::<OwnedHandle<ReadAtDisk<fs::File>>>();
+
+    let mut handle = open_pattern();
+    let contents = thread::spawn(move || {
+        let mut contents = Vec::new();
+        handle.read_to_end(&mut contents).unwrap();
+        contents
+    })
+    .join()
+    .unwrap();
+    assert_eq!(contents, fs::read("data/test_pattern.txt").unwrap());
+}
+
+#[test]
+fn owned_handles_with_options() {
+    let image = fs::read("basic.ext2").unwrap();
+    let fs = Arc::new(Ext2::new(std::io::Cursor::new(image)).unwrap());
+    let mut handle = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open_owned(&fs, "/owned.txt")
+        .unwrap();
+    thread::spawn(move || handle.write_all(b"from another thread").unwrap())
+        .join()
+        .unwrap();
+    let mut contents = String::new();
+    fs.open("/owned.txt")
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "from another thread");
+}
+
+#[test]
+fn one_handle_read_from_many_threads() {
+    let handle = Arc::new(open_pattern());
+    let expected = Arc::new(fs::read("data/test_pattern.txt").unwrap());
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let (handle, expected) = (handle.clone(), expected.clone());
+            thread::spawn(move || {
+                for j in 0..64 {
+                    let offset = (i * 997 + j * 1021) % 60000;
+                    let mut buf = [0; 3000];
+                    handle.read_exact_at(&mut buf, offset as u64).unwrap();
+                    assert_eq!(&buf[..], &expected[offset..offset + 3000]);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn parallel_reads_match_a_serial_read() {
+    // The workload of the parallel_read benchmark, checked byte for byte.
+    let size = 16 << 20;
+    let fs = Mkfs::new()
+        .format(Cursor::new(vec![0; size]), size as u64)
+        .unwrap();
+    for i in 0..16 {
+        let data: Vec<u8> = (0..100_000u32).map(|j| (j * (i + 1) % 251) as u8).collect();
+        fs.create(format!("/{}", i))
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+    }
+    let path = env::temp_dir().join(format!("ext2-parallel-{}.img", std::process::id()));
+    fs::write(&path, fs.into_inner().into_inner()).unwrap();
+    let fs = Arc::new(Ext2::new(ReadAtDisk::new(fs::File::open(&path).unwrap())).unwrap());
+    fs::remove_file(&path).unwrap();
+
+    let serial: Vec<Vec<u8>> = (0..16)
+        .map(|i| {
+            let mut contents = Vec::new();
+            fs.open(format!("/{}", i))
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            contents
+        })
+        .collect();
+    let serial = Arc::new(serial);
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let (fs, serial) = (fs.clone(), serial.clone());
+            thread::spawn(move || {
+                for i in (t..16).step_by(8).chain(0..16) {
+                    let handle = fs.open(format!("/{}", i)).unwrap();
+                    let mut contents = vec![0; serial[i].len()];
+                    // Unaligned chunks, so that reads straddle blocks.
+                    for (n, chunk) in contents.chunks_mut(7000).enumerate() {
+                        handle.read_exact_at(chunk, n as u64 * 7000).unwrap();
+                    }
+                    assert_eq!(contents, serial[i]);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::io::{self, Read, Write};
+
+mod common;
+use common::{debugfs, formatted, fsck, scratch};
+use ext2::Mkfs;
+
+fn read_to_string<T: ext2::ReadDisk>(fs: &ext2::Ext2<T>, path: &str) -> String {
+    let mut buf = String::new();
+    fs.open(path).unwrap().read_to_string(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn create_nested_dirs() {
+    let fs = scratch();
+    fs.create_dir("/etc").unwrap();
+    fs.create_dir("/etc/init.d").unwrap();
+    fs.create("/etc/init.d/rcS")
+        .unwrap()
+        .write_all(b"#!/bin/sh\n")
+        .unwrap();
+    assert_eq!(read_to_string(&fs, "/etc/init.d/rcS"), "#!/bin/sh\n");
+    let err = fs.create_dir("/etc").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    let err = fs.create_dir("/missing/etc").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    fsck(fs, "mkdir");
+}
+
+#[test]
+fn remove_files_and_dirs() {
+    let fs = scratch();
+    let free_before = fs
+        .allocator()
+        .unwrap()
+        .block_bitmap(0)
+        .unwrap()
+        .count_clear();
+    assert_eq!(
+        fs.remove_file("/sub").unwrap_err().kind(),
+        io::ErrorKind::IsADirectory
+    );
+    assert_eq!(
+        fs.remove_dir("/hello.txt").unwrap_err().kind(),
+        io::ErrorKind::NotADirectory
+    );
+    assert_eq!(
+        fs.remove_dir("/sub/pattern").unwrap_err().kind(),
+        io::ErrorKind::DirectoryNotEmpty
+    );
+    fs.remove_file("/sub/pattern/test_pattern.txt").unwrap();
+    fs.remove_dir("/sub/pattern").unwrap();
+    fs.remove_file("/hello.txt").unwrap();
+    assert_eq!(
+        fs.open("/hello.txt").err().unwrap().kind(),
+        io::ErrorKind::NotFound
+    );
+    assert_eq!(
+        fs.remove_file("/hello.txt").unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+    // 16 data blocks and one indirect block for the pattern, one block for
+    // its directory, and one for hello.txt.
+    let free_after = fs
+        .allocator()
+        .unwrap()
+        .block_bitmap(0)
+        .unwrap()
+        .count_clear();
+    assert_eq!(free_after - free_before, 19);
+    fsck(fs, "remove");
+}
+
+#[test]
+fn remove_device_nodes() {
+    let commands = ["mknod null c 1 3", "mknod sda b 8 0", "mknod fifo p"];
+    let fs = match debugfs(&commands) {
+        Some(fs) => fs,
+        None => return,
+    };
+    // i_block holds the device number, not blocks to free.
+    for path in &["/null", "/sda", "/fifo"] {
+        fs.remove_file(path).unwrap();
+    }
+    assert_eq!(fs.read_dir("/").unwrap().count(), 4);
+    fsck(fs, "remove-devices");
+}
+
+#[test]
+fn damaged_file_keeps_its_entry() {
+    let fs = match debugfs(&["sif /hello.txt block[0] 5000"]) {
+        Some(fs) => fs,
+        None => return,
+    };
+    let err = fs.remove_file("/hello.txt").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let names: Vec<_> = fs
+        .read_dir("/")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_owned())
+        .collect();
+    assert!(names.iter().any(|name| *name == "hello.txt"));
+}
+
+#[test]
+fn parent_without_links() {
+    let fs = match debugfs(&["mkdir sub/empty", "sif /sub links_count 0"]) {
+        Some(fs) => fs,
+        None => return,
+    };
+    let err = fs.remove_dir("/sub/empty").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    fs.create_dir("/empty").unwrap();
+    let err = fs.rename("/empty", "/sub/empty").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(fs.read_dir("/sub").unwrap().count(), 3);
+}
+
+#[test]
+fn failed_creates_free_their_inodes() {
+    let fs = scratch();
+    let mut f = fs.create("/big.bin").unwrap();
+    let err = f.write_all(&vec![0; 64 * 4096]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    drop(f);
+    // Fill the root directory's block with links, which need no inodes,
+    // down to the last gap that would fit a four byte name.
+    for prefix in &["x".repeat(200), "".to_owned()] {
+        for i in 0.. {
+            let link = format!("/{}{:04}", prefix, i);
+            if let Err(err) = fs.hard_link("/hello.txt", &link) {
+                assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+                break;
+            }
+        }
+    }
+
+    // Each gets an inode, but no room for its entry.
+    let err = fs.create("/file").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    let err = fs.symlink("hello.txt", "/link").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    let err = fs.create_dir("/dir").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    fsck(fs, "failed-creates");
+}
+
+#[test]
+fn hard_links_share_data() {
+    let fs = scratch();
+    fs.hard_link("/hello.txt", "/sub/hello-again.txt").unwrap();
+    fs.remove_file("/hello.txt").unwrap();
+    assert_eq!(
+        read_to_string(&fs, "/sub/hello-again.txt"),
+        "Hello world!\n"
+    );
+    assert_eq!(
+        fs.hard_link("/sub", "/sub2").unwrap_err().kind(),
+        io::ErrorKind::PermissionDenied
+    );
+    assert_eq!(
+        fs.hard_link("/goodbye.txt", "/sub/hello-again.txt")
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::AlreadyExists
+    );
+    fsck(fs, "link");
+}
+
+#[test]
+fn rename_files() {
+    let fs = scratch();
+    fs.rename("/hello.txt", "/hi.txt").unwrap();
+    fs.rename("/hi.txt", "/sub/pattern/hi.txt").unwrap();
+    assert!(fs.open("/hello.txt").is_err());
+    assert_eq!(read_to_string(&fs, "/sub/pattern/hi.txt"), "Hello world!\n");
+    fs.rename("/goodbye.txt", "/sub/pattern/hi.txt").unwrap();
+    assert!(fs.open("/goodbye.txt").is_err());
+    assert_eq!(
+        read_to_string(&fs, "/sub/pattern/hi.txt"),
+        "Adios Pkunkos!\n"
+    );
+    assert_eq!(
+        fs.rename("/sub/pattern/hi.txt", "/sub").unwrap_err().kind(),
+        io::ErrorKind::IsADirectory
+    );
+    fsck(fs, "rename-file");
+}
+
+#[test]
+fn rename_dirs() {
+    let fs = scratch();
+    assert_eq!(
+        fs.rename("/sub", "/sub/pattern/sub").unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        fs.rename("/sub", "/hello.txt").unwrap_err().kind(),
+        io::ErrorKind::NotADirectory
+    );
+    fs.rename("/sub/pattern", "/pattern").unwrap();
+    assert!(fs.open("/pattern/test_pattern.txt").is_ok());
+    fs.create_dir("/empty").unwrap();
+    fs.rename("/pattern", "/empty").unwrap();
+    assert!(fs.open("/empty/test_pattern.txt").is_ok());
+    assert_eq!(
+        fs.rename("/sub", "/empty").unwrap_err().kind(),
+        io::ErrorKind::DirectoryNotEmpty
+    );
+    fsck(fs, "rename-dir");
+}
+
+#[test]
+fn directories_grow_and_shrink() {
+    let fs = scratch();
+    let names: Vec<_> = (0..40)
+        .map(|i| format!("/sub/{}{:03}", "x".repeat(200), i))
+        .collect();
+    for name in &names {
+        fs.hard_link("/goodbye.txt", name).unwrap();
+    }
+    for name in &names {
+        fs.remove_file(name).unwrap();
+    }
+    fsck(fs, "grow");
+}
+
+#[test]
+fn lookup_in_large_directory() {
+    let fs = formatted(Mkfs::new().block_size(1024), 8 << 20);
+    fs.create_dir("/big").unwrap();
+    // Fifteen blocks of entries, reaching into the indirect block.
+    let names: Vec<_> = (0..600).map(|i| format!("/big/file-{:05}", i)).collect();
+    for name in &names {
+        fs.create(name).unwrap().write_all(name.as_bytes()).unwrap();
+    }
+    for name in &names {
+        assert_eq!(&read_to_string(&fs, name), name);
+    }
+    fsck(fs, "big-dir");
+}
+
+#[test]
+fn directories_in_64k_blocks() {
+    // A record filling a whole block is 65536 bytes long, one more than its
+    // 16-bit length on disk can hold.
+    let fs = formatted(Mkfs::new().block_size(65536), 16 << 20);
+    fs.create_dir("/dir").unwrap();
+    fs.create("/dir/file").unwrap();
+    let names: Vec<_> = (0..300)
+        .map(|i| format!("/dir/{}{:03}", "x".repeat(240), i))
+        .collect();
+    for name in &names {
+        fs.hard_link("/dir/file", name).unwrap();
+    }
+    assert_eq!(fs.metadata("/dir").unwrap().len(), 2 * 65536);
+    for name in &names {
+        fs.remove_file(name).unwrap();
+    }
+    fs.remove_file("/dir/file").unwrap();
+    fs.remove_dir("/dir").unwrap();
+    fsck(fs, "dirs-64k");
+}
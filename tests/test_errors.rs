@@ -0,0 +1,123 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+
+use byteorder::{ByteOrder, LE};
+use ext2::{Ext2, Ext2Error};
+
+mod common;
+use common::scratch;
+
+/// Where basic.ext2 keeps its inode table and root directory.
+const INODE_TABLE: usize = 4 * 4096;
+const ROOT_DIR: usize = 5 * 4096;
+
+/// basic.ext2, damaged by `damage` before it is mounted.
+fn corrupted<F: FnOnce(&mut [u8])>(damage: F) -> Ext2<Cursor<Vec<u8>>> {
+    let mut image = fs::read("basic.ext2").unwrap();
+    damage(&mut image);
+    Ext2::new(Cursor::new(image)).unwrap()
+}
+
+/// Offset of the root directory entry named `name`.
+fn root_entry(image: &[u8], name: &str) -> usize {
+    let mut offset = ROOT_DIR;
+    loop {
+        let name_len = image[offset + 6] as usize;
+        if &image[offset + 8..offset + 8 + name_len] == name.as_bytes() {
+            return offset;
+        }
+        offset += LE::read_u16(&image[offset + 4..offset + 6]) as usize;
+    }
+}
+
+fn assert_corrupt(err: io::Error) {
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match Ext2Error::of(&err) {
+        Some(Ext2Error::Corrupt { .. }) => {}
+        other => panic!("expected a corruption error, got {:?}", other),
+    }
+}
+
+#[test]
+fn invalid_mode() {
+    let fs = corrupted(|image| {
+        let hello = INODE_TABLE + 11 * 128;
+        LE::write_u16(&mut image[hello..hello + 2], 0xf1a4);
+    });
+    assert_corrupt(fs.open("/hello.txt").err().unwrap());
+    assert!(fs.open("/goodbye.txt").is_ok());
+}
+
+#[test]
+fn invalid_rec_len() {
+    let fs = corrupted(|image| {
+        let hello = root_entry(image, "hello.txt");
+        LE::write_u16(&mut image[hello + 4..hello + 6], 6);
+    });
+    assert_corrupt(fs.open("/goodbye.txt").err().unwrap());
+    let entries: Vec<_> = fs.read_dir("/").unwrap().collect();
+    assert_corrupt(entries.into_iter().find_map(Result::err).unwrap());
+    assert!(fs.walk("/").any(|entry| entry.is_err()));
+}
+
+#[test]
+fn oversized_directory() {
+    // A root directory of 2^32 - 1 blocks, for which 17GB of block
+    // numbers were once allocated up front.
+    let fs = corrupted(|image| {
+        let root = INODE_TABLE + 128;
+        LE::write_u32(&mut image[root + 4..root + 8], 0xffff_f000);
+        LE::write_u32(&mut image[root + 108..root + 112], 0xfff);
+    });
+    assert_corrupt(fs.open("/hello.txt").err().unwrap());
+    assert_corrupt(fs.read_dir("/").err().unwrap());
+    assert_corrupt(fs.walk("/").find_map(Result::err).unwrap());
+}
+
+#[test]
+fn inode_out_of_range() {
+    let fs = corrupted(|image| {
+        let hello = root_entry(image, "hello.txt");
+        LE::write_u32(&mut image[hello..hello + 4], 99_999);
+    });
+    assert_corrupt(fs.open("/hello.txt").err().unwrap());
+    let entry = fs
+        .read_dir("/")
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.file_name() == "hello.txt")
+        .unwrap();
+    assert_corrupt(entry.metadata().unwrap_err());
+}
+
+#[test]
+fn typed_errors() {
+    let fs = scratch();
+    let err = fs.open("/missing/file").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    match Ext2Error::of(&err) {
+        Some(Ext2Error::NotFound(path)) => assert_eq!(path, Path::new("/missing/file")),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+    let err = fs.read_dir("/hello.txt").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotADirectory);
+
+    let mut file = fs.create("/big").unwrap();
+    let err = loop {
+        if let Err(err) = file.write_all(&[0xaa; 4096]) {
+            break err;
+        }
+    };
+    assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    assert!(matches!(Ext2Error::of(&err), Some(Ext2Error::NoSpace)));
+
+    // Converting back and forth keeps the typed error.
+    let err = Ext2Error::from(err);
+    assert!(matches!(err, Ext2Error::NoSpace));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::StorageFull);
+}
@@ -0,0 +1,72 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ext2::{BlockGroupDescriptor, Ext2, FileType};
+
+mod common;
+use common::scratch;
+
+#[test]
+fn file_metadata() {
+    let fs = scratch();
+    let metadata = fs.metadata("/hello.txt").unwrap();
+    assert!(metadata.is_file());
+    assert_eq!(metadata.file_type(), FileType::File);
+    assert_eq!(metadata.len(), 13);
+    assert_eq!(metadata.ino(), 12);
+    assert_eq!(metadata.mode(), 0o100644);
+    assert_eq!(metadata.permissions().mode(), 0o644);
+    assert!(!metadata.permissions().readonly());
+    assert_eq!((metadata.uid(), metadata.gid()), (0, 0));
+    assert_eq!(metadata.nlink(), 1);
+    assert_eq!(metadata.blocks(), 8);
+    let mtime = UNIX_EPOCH + Duration::from_secs(0x5b9f0a6c);
+    assert_eq!(metadata.modified(), mtime);
+    assert_eq!(metadata.accessed(), mtime);
+    assert_eq!(metadata.changed(), mtime);
+    assert_eq!(fs.symlink_metadata("/hello.txt").unwrap(), metadata);
+}
+
+#[test]
+fn dir_metadata() {
+    let fs = scratch();
+    let metadata = fs.metadata("/sub").unwrap();
+    assert!(metadata.is_dir());
+    assert_eq!(metadata.permissions().mode(), 0o755);
+    assert_eq!(metadata.nlink(), 3);
+    assert_eq!(
+        fs.metadata("/missing").unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn handle_metadata_tracks_writes() {
+    let fs = scratch();
+    let before = SystemTime::now() - Duration::from_secs(1);
+    let mut file = fs.create("/new.txt").unwrap();
+    assert_eq!(file.metadata().unwrap().len(), 0);
+    file.write_all(b"twelve bytes").unwrap();
+    let metadata = file.metadata().unwrap();
+    assert_eq!(metadata.len(), 12);
+    assert!(metadata.modified() >= before);
+    assert_eq!(metadata, fs.metadata("/new.txt").unwrap());
+}
+
+#[test]
+fn high_uid_and_gid() {
+    let mut image = fs::read("basic.ext2").unwrap();
+    let descriptor = BlockGroupDescriptor::new(&image[4096..4128]).unwrap();
+    // The Linux l_i_uid_high and l_i_gid_high fields of inode 12.
+    let inode = descriptor.bg_inode_table as usize * 4096 + 11 * 128;
+    image[inode + 116 + 4..inode + 116 + 8].copy_from_slice(&[1, 0, 2, 0]);
+    let fs = Ext2::new(Cursor::new(image)).unwrap();
+    let metadata = fs.metadata("/hello.txt").unwrap();
+    assert_eq!(metadata.uid(), 1 << 16);
+    assert_eq!(metadata.gid(), 2 << 16);
+}
@@ -0,0 +1,143 @@
+#![cfg(test)]
+
+extern crate ext2;
+extern crate uuid;
+
+use std::io::{self, Cursor, Read, Write};
+
+use ext2::Mkfs;
+use uuid::Uuid;
+
+mod common;
+use common::{formatted, fsck};
+
+#[test]
+fn format_defaults() {
+    let fs = formatted(&Mkfs::new(), 8 << 20);
+    assert_eq!(fs.block_size().unwrap(), 4096);
+    assert!(fs.open("/lost+found").is_ok());
+    fsck(fs, "mkfs-default");
+}
+
+#[test]
+fn format_many_groups() {
+    let fs = formatted(Mkfs::new().block_size(1024), 40 << 20);
+    fs.create_dir("/dir").unwrap();
+    fsck(fs, "mkfs-groups");
+
+    let fs = formatted(Mkfs::new().block_size(1024).sparse_super(false), 40 << 20);
+    fsck(fs, "mkfs-dense");
+}
+
+#[test]
+fn format_large_blocks() {
+    // Groups are capped at 65528 blocks and inodes, short of the 65536
+    // bits in a bitmap block.
+    for &(block_size, size) in &[(8192, 8 << 20), (65536, 16 << 20)] {
+        let fs = formatted(Mkfs::new().block_size(block_size), size);
+        assert_eq!(fs.block_size().unwrap(), block_size);
+        fs.create("/file")
+            .unwrap()
+            .write_all(&vec![7; 3 * block_size as usize])
+            .unwrap();
+        fsck(fs, "mkfs-large-blocks");
+    }
+    let fs = formatted(
+        Mkfs::new().block_size(8192).inodes_per_group(70000),
+        32 << 20,
+    );
+    fsck(fs, "mkfs-inodes-capped");
+}
+
+#[test]
+fn format_64k_blocks() {
+    // Empty directory blocks hold one record of 65536 bytes, stored as 65535.
+    let fs = formatted(Mkfs::new().block_size(65536), 16 << 20);
+    assert_eq!(fs.read_dir("/lost+found").unwrap().count(), 0);
+    fs.create_dir("/dir").unwrap();
+    fs.create_dir("/dir/sub").unwrap();
+    assert_eq!(fs.read_dir("/dir").unwrap().count(), 1);
+    fs.remove_dir("/dir/sub").unwrap();
+    fs.remove_dir("/dir").unwrap();
+    assert_eq!(fs.read_dir("/").unwrap().count(), 1);
+    fsck(fs, "mkfs-64k");
+}
+
+#[test]
+fn format_revision_0() {
+    let fs = formatted(
+        Mkfs::new()
+            .revision(0)
+            .filetype(false)
+            .sparse_super(false)
+            .large_file(false),
+        4 << 20,
+    );
+    fs.create("/file")
+        .unwrap()
+        .write_all(b"old school\n")
+        .unwrap();
+    fsck(fs, "mkfs-rev0");
+}
+
+#[test]
+fn format_large_inodes() {
+    let fs = formatted(
+        Mkfs::new()
+            .inode_size(256)
+            .inodes_per_group(100)
+            .ext_attr(true)
+            .dir_index(true),
+        8 << 20,
+    );
+    fsck(fs, "mkfs-inodes");
+}
+
+#[test]
+fn format_label_and_uuid() {
+    let uuid = Uuid::from_bytes([
+        0x6b, 0x1f, 0x3a, 0x2e, 0x55, 0x0c, 0x4d, 0x8e, 0x9a, 0x10, 0x37, 0x2c, 0x84, 0xd1, 0x0e,
+        0x42,
+    ]);
+    let fs = formatted(Mkfs::new().volume_label("rootfs").uuid(uuid), 8 << 20);
+    let image = fs.into_inner().into_inner();
+    let sb = ext2::Superblock::new(&image[1024..2048]).unwrap();
+    assert_eq!(&sb.s_volume_name[..7], b"rootfs\0");
+    assert_eq!(sb.s_uuid, *uuid.as_bytes());
+}
+
+#[test]
+fn format_then_write() {
+    let fs = formatted(Mkfs::new().block_size(2048), 16 << 20);
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7) as u8).collect();
+    fs.create_dir("/data").unwrap();
+    fs.create("/data/blob").unwrap().write_all(&data).unwrap();
+    let mut buf = Vec::new();
+    fs.open("/data/blob")
+        .unwrap()
+        .read_to_end(&mut buf)
+        .unwrap();
+    assert_eq!(buf, data);
+    fsck(fs, "mkfs-write");
+}
+
+#[test]
+fn format_errors() {
+    let format = |options: &Mkfs, size: usize| {
+        options
+            .format(Cursor::new(vec![0; size]), size as u64)
+            .err()
+            .unwrap()
+            .kind()
+    };
+    let invalid = io::ErrorKind::InvalidInput;
+    assert_eq!(format(Mkfs::new().block_size(3000), 1 << 20), invalid);
+    assert_eq!(format(Mkfs::new().inode_size(100), 1 << 20), invalid);
+    assert_eq!(format(Mkfs::new().revision(2), 1 << 20), invalid);
+    assert_eq!(format(Mkfs::new().reserved_ratio(75.0), 1 << 20), invalid);
+    assert_eq!(
+        format(Mkfs::new().volume_label("far too long a label"), 1 << 20),
+        invalid
+    );
+    assert_eq!(format(&Mkfs::new(), 16 << 10), invalid);
+}
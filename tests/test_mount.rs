@@ -0,0 +1,131 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::fs;
+use std::io::{self, Cursor, Read};
+
+use byteorder::{ByteOrder, LE};
+use ext2::{Ext2, Ext2Error, Mkfs, RoCompatFeatures};
+
+/// Where the primary superblock starts.
+const SUPERBLOCK: usize = 1024;
+
+/// Mount basic.ext2 after `damage` has been done to its superblock.
+fn mount<F: FnOnce(&mut [u8])>(damage: F) -> io::Result<Ext2<Cursor<Vec<u8>>>> {
+    let mut image = fs::read("basic.ext2").unwrap();
+    damage(&mut image[SUPERBLOCK..SUPERBLOCK + 1024]);
+    Ext2::new(Cursor::new(image))
+}
+
+fn set_u32(sb: &mut [u8], offset: usize, value: u32) {
+    LE::write_u32(&mut sb[offset..offset + 4], value);
+}
+
+#[test]
+fn not_ext2() {
+    let err = mount(|sb| LE::write_u16(&mut sb[56..58], 0x1234))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("bad magic number 0x1234"));
+
+    let err = Ext2::new(Cursor::new(vec![0; 8192])).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(Ext2::new(Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn impossible_geometry() {
+    for &(offset, value) in &[(24, 30), (32, 0), (40, 0), (40, 1 << 20), (20, 1)] {
+        let err = mount(|sb| set_u32(sb, offset, value)).err().unwrap();
+        assert!(
+            matches!(Ext2Error::of(&err), Some(Ext2Error::Corrupt { .. })),
+            "field at {} set to {}: {}",
+            offset,
+            value,
+            err
+        );
+    }
+}
+
+#[test]
+fn oversized_groups() {
+    // With 64KiB blocks a bitmap has room for 524288 bits, but the 16-bit
+    // free counts in the group descriptors only for 65535.
+    let fs = Mkfs::new()
+        .block_size(65536)
+        .format(Cursor::new(vec![0; 16 << 20]), 16 << 20)
+        .unwrap();
+    let image = fs.into_inner().into_inner();
+    let remount = |offsets: &[usize]| {
+        let mut image = image.clone();
+        for &offset in offsets {
+            set_u32(&mut image[SUPERBLOCK..], offset, 65536);
+        }
+        Ext2::new(Cursor::new(image))
+    };
+    assert!(remount(&[]).is_ok());
+    for offsets in &[&[32][..], &[0, 40]] {
+        let err = remount(offsets).err().unwrap();
+        assert!(
+            matches!(Ext2Error::of(&err), Some(Ext2Error::Corrupt { .. })),
+            "{:?}: {}",
+            offsets,
+            err
+        );
+    }
+}
+
+#[test]
+fn unknown_revision() {
+    let err = mount(|sb| set_u32(sb, 76, 2)).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn incompatible_features() {
+    // extent and 64bit, as on a typical ext4 filesystem, and a bit nobody
+    // has named yet.
+    let err = mount(|sb| set_u32(sb, 96, 0x2 | 0x40 | 0x80 | 1 << 30))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    match Ext2Error::of(&err) {
+        Some(Ext2Error::Unsupported(features)) => {
+            assert_eq!(features, "extent 64bit FEATURE_I30")
+        }
+        other => panic!("expected Unsupported, got {:?}", other),
+    }
+}
+
+#[test]
+fn read_only_features() {
+    let fs = mount(|sb| set_u32(sb, 100, 0x1 | 0x2 | 0x20)).unwrap();
+    assert!(fs.is_read_only());
+    assert_eq!(
+        fs.read_only_features().unwrap(),
+        RoCompatFeatures::DIR_NLINK
+    );
+
+    let mut contents = String::new();
+    fs.open("/hello.txt")
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "Hello world!\n");
+    assert_eq!(fs.read_dir("/").unwrap().count(), 4);
+
+    for err in [
+        fs.create("/new").err().unwrap(),
+        fs.create_dir("/new").unwrap_err(),
+        fs.remove_file("/hello.txt").unwrap_err(),
+        fs.symlink("hello.txt", "/link").unwrap_err(),
+    ] {
+        assert_eq!(err.kind(), io::ErrorKind::ReadOnlyFilesystem);
+    }
+
+    let fs = mount(|_| {}).unwrap();
+    assert!(!fs.is_read_only());
+    assert!(fs.read_only_features().unwrap().is_empty());
+}
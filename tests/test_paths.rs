@@ -0,0 +1,113 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use ext2::OpenOptions;
+
+mod common;
+use common::{fsck, scratch};
+
+fn read_to_string<T: ext2::ReadDisk>(mut file: ext2::handle::Ext2Handle<T>) -> String {
+    let mut buf = String::new();
+    file.read_to_string(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn relative_paths_start_at_root() {
+    let fs = scratch();
+    assert_eq!(
+        read_to_string(fs.open("hello.txt").unwrap()),
+        "Hello world!\n"
+    );
+    assert!(fs.metadata("sub/pattern").unwrap().is_dir());
+    fs.create_dir("new").unwrap();
+    assert!(fs.metadata("/new").unwrap().is_dir());
+}
+
+#[test]
+fn dot_and_dot_dot() {
+    let fs = scratch();
+    assert_eq!(
+        read_to_string(fs.open("/sub/./pattern/../../hello.txt").unwrap()),
+        "Hello world!\n"
+    );
+    assert_eq!(
+        read_to_string(fs.open("/../../hello.txt").unwrap()),
+        "Hello world!\n"
+    );
+    assert_eq!(
+        fs.open("/hello.txt/..").err().unwrap().kind(),
+        io::ErrorKind::NotADirectory
+    );
+    assert_eq!(
+        fs.open("/hello.txt/anything").err().unwrap().kind(),
+        io::ErrorKind::NotADirectory
+    );
+}
+
+#[test]
+fn canonicalize() {
+    let fs = scratch();
+    fs.symlink("sub/pattern", "/pat").unwrap();
+    fs.symlink("../../goodbye.txt", "/sub/pattern/bye").unwrap();
+    let canonical = |path: &str| fs.canonicalize(path).unwrap();
+    assert_eq!(canonical("/"), Path::new("/"));
+    assert_eq!(canonical("/.."), Path::new("/"));
+    assert_eq!(canonical("sub//pattern/."), Path::new("/sub/pattern"));
+    assert_eq!(canonical("/pat"), Path::new("/sub/pattern"));
+    assert_eq!(
+        canonical("/pat/../michelle.jpg"),
+        Path::new("/sub/michelle.jpg")
+    );
+    assert_eq!(canonical("/pat/bye"), Path::new("/goodbye.txt"));
+    assert_eq!(
+        fs.canonicalize("/pat/missing").unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn open_at_directory() {
+    let fs = scratch();
+    let sub = fs.open("/sub").unwrap();
+    let pattern = fs.open_at(&sub, "pattern/test_pattern.txt").unwrap();
+    assert_eq!(pattern.path(), Path::new("/sub/pattern/test_pattern.txt"));
+    assert_eq!(
+        read_to_string(fs.open_at(&sub, "../goodbye.txt").unwrap()),
+        "Adios Pkunkos!\n"
+    );
+    assert_eq!(
+        read_to_string(fs.open_at(&sub, "/hello.txt").unwrap()),
+        "Hello world!\n"
+    );
+
+    // Backing out of a directory opened somewhere deeper.
+    let inner = fs.open("/sub/pattern").unwrap();
+    assert_eq!(
+        read_to_string(fs.open_at(&inner, "../../hello.txt").unwrap()),
+        "Hello world!\n"
+    );
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open_at(&fs, &inner, "created.txt")
+        .unwrap()
+        .write_all(b"relative\n")
+        .unwrap();
+    assert_eq!(
+        read_to_string(fs.open("/sub/pattern/created.txt").unwrap()),
+        "relative\n"
+    );
+
+    let file = fs.open("/hello.txt").unwrap();
+    assert_eq!(
+        fs.open_at(&file, "x").err().unwrap().kind(),
+        io::ErrorKind::NotADirectory
+    );
+    fsck(fs, "open-at");
+}
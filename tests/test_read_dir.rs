@@ -0,0 +1,108 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::io::{self, Write};
+
+use ext2::{FileType, Mkfs};
+
+mod common;
+use common::{formatted, scratch};
+
+fn list<T: ext2::ReadDisk>(fs: &ext2::Ext2<T>, path: &str) -> Vec<(String, FileType)> {
+    let mut entries: Vec<_> = fs
+        .read_dir(path)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (
+                entry.file_name().to_str().unwrap().to_owned(),
+                entry.file_type().unwrap(),
+            )
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+#[test]
+fn list_root() {
+    let fs = scratch();
+    assert_eq!(
+        list(&fs, "/"),
+        vec![
+            ("goodbye.txt".to_owned(), FileType::File),
+            ("hello.txt".to_owned(), FileType::File),
+            ("lost+found".to_owned(), FileType::Directory),
+            ("sub".to_owned(), FileType::Directory),
+        ]
+    );
+}
+
+#[test]
+fn entry_paths_and_metadata() {
+    let fs = scratch();
+    let entries: Vec<_> = fs.read_dir("/sub").unwrap().map(Result::unwrap).collect();
+    let names: Vec<_> = entries.iter().map(|entry| entry.path()).collect();
+    assert!(names.iter().any(|path| path.as_os_str() == "/sub/pattern"));
+    let hello = fs
+        .read_dir("/")
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.file_name() == "hello.txt")
+        .unwrap();
+    assert_eq!(hello.ino(), 12);
+    let metadata = hello.metadata().unwrap();
+    assert!(metadata.is_file());
+    assert_eq!(metadata.len(), 13);
+    assert_eq!(metadata.ino(), 12);
+}
+
+#[test]
+fn read_dir_errors() {
+    let fs = scratch();
+    assert_eq!(
+        fs.read_dir("/hello.txt").err().unwrap().kind(),
+        io::ErrorKind::NotADirectory
+    );
+    assert_eq!(
+        fs.read_dir("/missing").err().unwrap().kind(),
+        io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn list_without_filetype_feature() {
+    let fs = formatted(Mkfs::new().filetype(false), 8 << 20);
+    fs.create_dir("/dir").unwrap();
+    fs.create("/file").unwrap().write_all(b"data").unwrap();
+    assert_eq!(
+        list(&fs, "/"),
+        vec![
+            ("dir".to_owned(), FileType::Directory),
+            ("file".to_owned(), FileType::File),
+            ("lost+found".to_owned(), FileType::Directory),
+        ]
+    );
+}
+
+#[test]
+fn list_large_directory() {
+    let fs = formatted(Mkfs::new().block_size(1024), 8 << 20);
+    fs.create_dir("/big").unwrap();
+    for i in 0..500 {
+        fs.create(format!("/big/{:04}", i)).unwrap();
+    }
+    for i in (0..500).step_by(3) {
+        fs.remove_file(format!("/big/{:04}", i)).unwrap();
+    }
+    let names: Vec<_> = list(&fs, "/big")
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    let expected: Vec<_> = (0..500)
+        .filter(|i| i % 3 != 0)
+        .map(|i| format!("{:04}", i))
+        .collect();
+    assert_eq!(names, expected);
+}
@@ -0,0 +1,41 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::fs::{self, File};
+use std::io::{Cursor, Read};
+
+use ext2::{BlockCache, Ext2, ReadDisk, ReadOnlyDisk};
+
+fn hello<T: ReadDisk>(fs: &Ext2<T>) -> String {
+    let mut contents = String::new();
+    fs.open("/hello.txt")
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    contents
+}
+
+#[test]
+fn mount_borrowed_bytes() {
+    // A `Cursor<&[u8]>` can be read and sought, but not written.
+    let image = fs::read("basic.ext2").unwrap();
+    let fs = Ext2::new(Cursor::new(&image[..])).unwrap();
+    assert_eq!(hello(&fs), "Hello world!\n");
+    assert_eq!(fs.read_dir("/sub").unwrap().count(), 2);
+    assert_eq!(fs.walk("/").count(), 8);
+    assert!(fs.metadata("/sub/michelle.jpg").unwrap().is_file());
+}
+
+#[test]
+fn mount_read_only_file() {
+    let disk = ReadOnlyDisk::new(File::open("basic.ext2").unwrap());
+    let fs = Ext2::new(BlockCache::new(disk, 16)).unwrap();
+    assert_eq!(hello(&fs), "Hello world!\n");
+    let mut f = fs.open("/sub/michelle.jpg").unwrap();
+    f.set_read_ahead(4);
+    let mut contents = Vec::new();
+    f.read_to_end(&mut contents).unwrap();
+    assert_eq!(contents.len(), 75557);
+    assert_eq!(fs.with_disk(|cache| cache.stats().writebacks), 0);
+}
@@ -0,0 +1,78 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::fs;
+use std::io::{Cursor, Read, Write};
+
+use ext2::{BlockCache, Ext2, Mkfs, ReadDisk, SectorDisk, WriteDisk};
+
+mod common;
+use common::fsck;
+
+fn read_to_string<T: ReadDisk>(fs: &Ext2<T>, path: &str) -> String {
+    let mut contents = String::new();
+    fs.open(path)
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    contents
+}
+
+/// Fill a directory with files spanning several blocks each.
+fn write_files<T: WriteDisk>(fs: &Ext2<T>) {
+    fs.create_dir("/dir").unwrap();
+    for i in 0..20 {
+        let path = format!("/dir/{}", i);
+        let contents = format!("file {}\n", i).repeat(300);
+        fs.create(&path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+    assert_eq!(read_to_string(fs, "/dir/7"), "file 7\n".repeat(300));
+}
+
+#[test]
+fn mount_on_4k_sectors() {
+    let image = fs::read("basic.ext2").unwrap();
+    let fs = Ext2::new(SectorDisk::new(Cursor::new(image), 4096)).unwrap();
+    assert_eq!(read_to_string(&fs, "/hello.txt"), "Hello world!\n");
+    assert_eq!(fs.read_dir("/").unwrap().count(), 4);
+}
+
+#[test]
+fn small_blocks_on_large_sectors() {
+    // 1KiB blocks share their sectors with their neighbours, as does the
+    // superblock.
+    for &sector_size in &[512, 4096, 8192] {
+        let size = 4 << 20;
+        let disk = SectorDisk::new(Cursor::new(vec![0; size]), sector_size);
+        let fs = Mkfs::new()
+            .block_size(1024)
+            .format(disk, size as u64)
+            .unwrap();
+        write_files(&fs);
+        let image = fs.into_inner().into_inner().into_inner();
+        fsck(Ext2::new(Cursor::new(image)).unwrap(), "sectors");
+    }
+}
+
+#[test]
+fn block_cache_on_large_sectors() {
+    let size = 4 << 20;
+    let disk = SectorDisk::new(Cursor::new(vec![0; size]), 4096);
+    let fs = Mkfs::new()
+        .block_size(1024)
+        .format(BlockCache::new(disk, 16), size as u64)
+        .unwrap();
+    assert_eq!(fs.with_disk(|cache| cache.sector_size()), 4096);
+    write_files(&fs);
+    let image = fs
+        .into_inner()
+        .into_inner()
+        .unwrap()
+        .into_inner()
+        .into_inner();
+    fsck(Ext2::new(Cursor::new(image)).unwrap(), "cached-sectors");
+}
@@ -0,0 +1,234 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::process::Command;
+
+use ext2::{Ext2, Mkfs};
+
+mod common;
+use common::{formatted, fsck};
+
+const BS: u64 = 4096;
+
+/// Write `data` at each of `offsets` of a new file at `path`.
+fn write_sparse(fs: &Ext2<Cursor<Vec<u8>>>, path: &str, offsets: &[u64], data: &[u8]) {
+    let mut f = fs.create(path).unwrap();
+    for &offset in offsets {
+        f.seek(SeekFrom::Start(offset)).unwrap();
+        f.write_all(data).unwrap();
+    }
+}
+
+#[test]
+fn holes_read_as_zeros() {
+    let fs = formatted(Mkfs::new().block_size(BS as u32), 16 << 20);
+    write_sparse(&fs, "/sparse", &[0, 5 * BS + 10, 40 * BS], b"data");
+
+    let mut contents = Vec::new();
+    fs.open("/sparse")
+        .unwrap()
+        .read_to_end(&mut contents)
+        .unwrap();
+    let mut expected = vec![0; 40 * BS as usize + 4];
+    for &offset in &[0, 5 * BS as usize + 10, 40 * BS as usize] {
+        expected[offset..offset + 4].copy_from_slice(b"data");
+    }
+    assert_eq!(contents, expected);
+
+    // Only the three blocks written, and the indirect block, take space.
+    let meta = fs.open("/sparse").unwrap().metadata().unwrap();
+    assert_eq!(meta.blocks() as u64, 4 * BS / 512);
+    fsck(fs, "holes");
+}
+
+#[test]
+fn missing_indirect_block_is_a_hole() {
+    let fs = formatted(Mkfs::new().block_size(BS as u32), 16 << 20);
+    // The second write lands in the double indirect tree, leaving the
+    // single indirect pointer zero.
+    write_sparse(&fs, "/gap", &[0, 2000 * BS], b"data");
+
+    let f = fs.open("/gap").unwrap();
+    assert_eq!(f.size(), 2000 * BS + 4);
+    let mut buf = vec![0xff; 8 * BS as usize];
+    f.read_exact_at(&mut buf, 10 * BS).unwrap();
+    assert!(buf.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn next_data_and_next_hole() {
+    let fs = formatted(Mkfs::new().block_size(BS as u32), 16 << 20);
+    write_sparse(&fs, "/sparse", &[BS, 2 * BS, 20 * BS, 2000 * BS], b"data");
+    let f = fs.open("/sparse").unwrap();
+    let size = 2000 * BS + 4;
+
+    assert_eq!(f.next_data(0).unwrap(), Some(BS));
+    assert_eq!(f.next_data(BS + 7).unwrap(), Some(BS + 7));
+    assert_eq!(f.next_hole(BS).unwrap(), Some(3 * BS));
+    assert_eq!(f.next_data(3 * BS).unwrap(), Some(20 * BS));
+    assert_eq!(f.next_hole(20 * BS).unwrap(), Some(21 * BS));
+    assert_eq!(f.next_data(21 * BS).unwrap(), Some(2000 * BS));
+    assert_eq!(f.next_hole(0).unwrap(), Some(0));
+    assert_eq!(f.next_hole(2000 * BS).unwrap(), Some(size));
+    assert_eq!(f.next_data(size).unwrap(), None);
+    assert_eq!(f.next_hole(size).unwrap(), None);
+
+    // A file with no data at all is one hole.
+    write_sparse(&fs, "/empty", &[], b"");
+    let f = fs.open("/empty").unwrap();
+    assert_eq!(f.next_data(0).unwrap(), None);
+    assert_eq!(f.next_hole(0).unwrap(), None);
+}
+
+#[test]
+fn copy_only_the_data() {
+    let fs = formatted(Mkfs::new().block_size(BS as u32), 16 << 20);
+    write_sparse(&fs, "/sparse", &[3, 8 * BS - 2, 30 * BS], b"data");
+    let f = fs.open("/sparse").unwrap();
+
+    let mut extents = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = f.next_data(pos).unwrap() {
+        let end = f.next_hole(start).unwrap().unwrap();
+        extents.push((start, end));
+        pos = end;
+    }
+    assert_eq!(
+        extents,
+        vec![(0, BS), (7 * BS, 9 * BS), (30 * BS, 30 * BS + 4)]
+    );
+}
+
+/// Check that `f` holds `marker(offset)` at each of `offsets`, and nothing
+/// but zeros between them.
+fn check_markers<T: ext2::ReadDisk>(f: &ext2::handle::Ext2Handle<'_, T>, offsets: &[u64]) {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = f.next_data(pos).unwrap() {
+        let end = f.next_hole(start).unwrap().unwrap();
+        let mut data = vec![0; (end - start) as usize];
+        f.read_exact_at(&mut data, start).unwrap();
+        for (i, chunk) in data.chunks(16).enumerate() {
+            if chunk.iter().any(|&b| b != 0) {
+                found.push((start + i as u64 * 16, chunk.to_vec()));
+            }
+        }
+        pos = end;
+    }
+    let expected: Vec<_> = offsets
+        .iter()
+        .map(|&offset| (offset, marker(offset).to_vec()))
+        .collect();
+    assert_eq!(found, expected);
+}
+
+/// 16 bytes naming `offset`, to tell which block ended up where.
+fn marker(offset: u64) -> [u8; 16] {
+    let mut marker = [0; 16];
+    marker[..8].copy_from_slice(b"marker: ");
+    marker[8..].copy_from_slice(&offset.to_le_bytes());
+    marker
+}
+
+#[test]
+fn every_level_of_indirection() {
+    // With 1KiB blocks the triple indirect tree starts at block
+    // 12 + 256 + 256 * 256 and ends 256^3 blocks later, at 16GiB.
+    let fs = formatted(Mkfs::new().block_size(1024), 8 << 20);
+    let blocks = [
+        0, 11, 12, 267, 268, 300, 65803, 65804, 70000, 5_000_000, 16_843_019,
+    ];
+    let offsets: Vec<u64> = blocks.iter().map(|&block| block * 1024).collect();
+    let mut f = fs.create("/large").unwrap();
+    for &offset in &offsets {
+        f.seek(SeekFrom::Start(offset)).unwrap();
+        f.write_all(&marker(offset)).unwrap();
+    }
+    f.seek(SeekFrom::Start(16_843_020 * 1024)).unwrap();
+    let err = f.write_all(b"too far").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+    drop(f);
+
+    let f = fs.open("/large").unwrap();
+    assert_eq!(f.size(), 16_843_019 * 1024 + 16);
+    check_markers(&f, &offsets);
+    for &offset in &offsets {
+        let mut buf = [0; 16];
+        f.read_exact_at(&mut buf, offset).unwrap();
+        assert_eq!(buf, marker(offset));
+    }
+    drop(f);
+    fsck(fs, "indirection");
+}
+
+/// Build an image with `mke2fs -d` holding one sparse file of `size`
+/// bytes with a marker at each of `offsets`, or return `None` if
+/// e2fsprogs is not installed.
+fn mke2fs_sparse(block_size: u32, size: u64, offsets: &[u64]) -> Option<Vec<u8>> {
+    let dir = env::temp_dir().join(format!("ext2-sparse-{}", std::process::id()));
+    let root = dir.join("root");
+    fs::create_dir_all(&root).unwrap();
+    let mut file = File::create(root.join("sparse")).unwrap();
+    for &offset in offsets {
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&marker(offset)).unwrap();
+    }
+    file.set_len(size).unwrap();
+    let image = dir.join("image");
+    File::create(&image).unwrap().set_len(16 << 20).unwrap();
+    let output = Command::new("mke2fs")
+        .args(["-q", "-F", "-t", "ext2", "-b"])
+        .arg(block_size.to_string())
+        .arg("-d")
+        .arg(&root)
+        .arg(&image)
+        .output();
+    let result = match output {
+        Ok(output) => {
+            assert!(
+                output.status.success(),
+                "mke2fs failed:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            Some(fs::read(&image).unwrap())
+        }
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            eprintln!("mke2fs not installed; skipping");
+            None
+        }
+        Err(err) => panic!("could not run mke2fs: {}", err),
+    };
+    fs::remove_dir_all(&dir).unwrap();
+    result
+}
+
+#[test]
+fn multi_gigabyte_file_from_mke2fs() {
+    // With 4KiB blocks the double indirect tree starts at block 1036 and
+    // the triple indirect one at block 1049612, past 4GiB.
+    let blocks = [0, 12, 1035, 1036, 2060, 1_049_611, 1_049_612, 1_200_000];
+    let offsets: Vec<u64> = blocks.iter().map(|&block| block * 4096 + 1024).collect();
+    let size = 6 << 30;
+    let image = match mke2fs_sparse(4096, size, &offsets) {
+        Some(image) => image,
+        None => return,
+    };
+    let fs = Ext2::new(Cursor::new(image)).unwrap();
+    let f = fs.open("/sparse").unwrap();
+    assert_eq!(f.size(), size);
+    check_markers(&f, &offsets);
+
+    // Reading through the handle sees the same, holes and all.
+    let mut f = fs.open("/sparse").unwrap();
+    f.seek(SeekFrom::Start(1_049_611 * 4096)).unwrap();
+    let mut buf = vec![0; 2 * 4096];
+    f.read_exact(&mut buf).unwrap();
+    let mut expected = vec![0; 2 * 4096];
+    expected[1024..1040].copy_from_slice(&marker(1_049_611 * 4096 + 1024));
+    expected[5120..5136].copy_from_slice(&marker(1_049_612 * 4096 + 1024));
+    assert_eq!(buf, expected);
+}
@@ -0,0 +1,139 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::io::{self, Read};
+use std::path::Path;
+
+use ext2::FileType;
+
+mod common;
+use common::{debugfs, fsck, scratch};
+
+fn read_to_string<T: ext2::ReadDisk>(fs: &ext2::Ext2<T>, path: &str) -> String {
+    let mut buf = String::new();
+    fs.open(path).unwrap().read_to_string(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn fast_and_slow_links() {
+    let fs = scratch();
+    let long = format!("/{}/../hello.txt", "x".repeat(100));
+    fs.symlink("hello.txt", "/fast").unwrap();
+    fs.symlink(&long, "/slow").unwrap();
+    assert_eq!(fs.read_link("/fast").unwrap(), Path::new("hello.txt"));
+    assert_eq!(fs.read_link("/slow").unwrap(), Path::new(&long));
+    let fast = fs.symlink_metadata("/fast").unwrap();
+    assert!(fast.is_symlink());
+    assert_eq!(fast.len(), 9);
+    assert_eq!(fast.blocks(), 0);
+    assert!(fs.symlink_metadata("/slow").unwrap().blocks() > 0);
+    assert_eq!(
+        fs.read_link("/hello.txt").unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        fs.symlink("elsewhere", "/fast").unwrap_err().kind(),
+        io::ErrorKind::AlreadyExists
+    );
+    fsck(fs, "symlink");
+}
+
+#[test]
+fn fast_link_with_xattr_block() {
+    // The attribute block counts towards `i_blocks` but holds no part of
+    // the target, which stays in `i_block`.
+    let fs = match debugfs(&[
+        "symlink link hello.txt",
+        "sif link file_acl 63",
+        "sif link blocks 8",
+    ]) {
+        Some(fs) => fs,
+        None => return,
+    };
+    assert_eq!(fs.read_link("/link").unwrap(), Path::new("hello.txt"));
+    assert_eq!(
+        read_to_string(&fs, "/link"),
+        read_to_string(&fs, "/hello.txt")
+    );
+    fs.remove_file("/link").unwrap();
+}
+
+#[test]
+fn follow_links_in_paths() {
+    let fs = scratch();
+    fs.symlink("sub/pattern", "/pat").unwrap();
+    fs.symlink("/sub", "/sub/pattern/up").unwrap();
+    fs.symlink("../goodbye.txt", "/sub/bye").unwrap();
+    fs.symlink("bye", "/sub/bye-again").unwrap();
+    assert!(fs.open("/pat/test_pattern.txt").is_ok());
+    assert_eq!(read_to_string(&fs, "/sub/bye-again"), "Adios Pkunkos!\n");
+    assert_eq!(read_to_string(&fs, "/pat/up/bye"), "Adios Pkunkos!\n");
+    assert!(fs.metadata("/pat").unwrap().is_dir());
+    assert!(fs.symlink_metadata("/pat").unwrap().is_symlink());
+    assert_eq!(
+        fs.metadata("/pat/up").unwrap().ino(),
+        fs.metadata("/sub").unwrap().ino()
+    );
+    let names: Vec<_> = fs
+        .read_dir("/pat/up/pattern")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_owned())
+        .collect();
+    assert!(names.iter().any(|name| name == "test_pattern.txt"));
+    fsck(fs, "follow");
+}
+
+#[test]
+fn dangling_and_looping_links() {
+    let fs = scratch();
+    fs.symlink("/nowhere", "/dangling").unwrap();
+    fs.symlink("loop-b", "/loop-a").unwrap();
+    fs.symlink("loop-a", "/loop-b").unwrap();
+    assert_eq!(
+        fs.open("/dangling").err().unwrap().kind(),
+        io::ErrorKind::NotFound
+    );
+    assert_eq!(
+        fs.create("/dangling").err().unwrap().kind(),
+        io::ErrorKind::AlreadyExists
+    );
+    assert!(fs.symlink_metadata("/dangling").unwrap().is_symlink());
+    let err = fs.open("/loop-a").err().unwrap();
+    assert!(err.to_string().contains("too many levels"));
+    fs.remove_file("/loop-a").unwrap();
+    fs.remove_file("/dangling").unwrap();
+    assert_eq!(
+        fs.open("/loop-b").err().unwrap().kind(),
+        io::ErrorKind::NotFound
+    );
+    fsck(fs, "loop");
+}
+
+#[test]
+fn walk_links() {
+    let fs = scratch();
+    fs.symlink("..", "/sub/pattern/parent").unwrap();
+    fs.symlink("/hello.txt", "/sub/hello").unwrap();
+    let plain: Vec<_> = fs.walk("/sub").map(Result::unwrap).collect();
+    let link = plain
+        .iter()
+        .find(|entry| entry.path() == Path::new("/sub/hello"))
+        .unwrap();
+    assert_eq!(link.file_type(), FileType::SymLink);
+    assert!(link.path_is_symlink());
+    assert_eq!(plain.len(), 6);
+
+    let followed: Vec<_> = fs.walk("/sub").follow_links(true).collect();
+    let hello = followed
+        .iter()
+        .filter_map(|entry| entry.as_ref().ok())
+        .find(|entry| entry.path() == Path::new("/sub/hello"))
+        .unwrap();
+    assert_eq!(hello.file_type(), FileType::File);
+    assert!(hello.path_is_symlink());
+    assert_eq!(hello.ino(), 12);
+    // The link back up to /sub is reported rather than walked forever.
+    assert_eq!(followed.iter().filter(|entry| entry.is_err()).count(), 1);
+}
@@ -0,0 +1,118 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::io::Write;
+
+use ext2::{FileType, Mkfs};
+
+mod common;
+use common::{formatted, scratch};
+
+fn paths<T: ext2::ReadDisk>(walk: ext2::Walk<T>) -> Vec<String> {
+    walk.map(|entry| entry.unwrap().path().to_str().unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn walk_whole_tree() {
+    let fs = scratch();
+    let mut all = paths(fs.walk("/"));
+    assert_eq!(all[0], "/");
+    all.sort();
+    assert_eq!(
+        all,
+        vec![
+            "/",
+            "/goodbye.txt",
+            "/hello.txt",
+            "/lost+found",
+            "/sub",
+            "/sub/michelle.jpg",
+            "/sub/pattern",
+            "/sub/pattern/test_pattern.txt",
+        ]
+    );
+}
+
+#[test]
+fn walk_depths() {
+    let fs = scratch();
+    let entries: Vec<_> = fs.walk("/").map(Result::unwrap).collect();
+    for entry in &entries {
+        let components = entry.path().components().count() - 1;
+        assert_eq!(entry.depth(), components);
+    }
+    let mut shallow = paths(fs.walk("/").min_depth(1).max_depth(1));
+    shallow.sort();
+    assert_eq!(
+        shallow,
+        vec!["/goodbye.txt", "/hello.txt", "/lost+found", "/sub"]
+    );
+    assert_eq!(paths(fs.walk("/sub").max_depth(0)), vec!["/sub"]);
+    assert_eq!(
+        paths(fs.walk("/sub").min_depth(2)),
+        vec!["/sub/pattern/test_pattern.txt"]
+    );
+}
+
+#[test]
+fn walk_orders() {
+    let fs = scratch();
+    let position = |paths: &[String], path: &str| paths.iter().position(|p| p == path).unwrap();
+    let pre = paths(fs.walk("/"));
+    assert!(position(&pre, "/sub") < position(&pre, "/sub/pattern"));
+    assert!(position(&pre, "/sub/pattern") < position(&pre, "/sub/pattern/test_pattern.txt"));
+    let post = paths(fs.walk("/").contents_first(true));
+    assert_eq!(post.last().unwrap(), "/");
+    assert!(position(&post, "/sub") > position(&post, "/sub/pattern"));
+    assert!(position(&post, "/sub/pattern") > position(&post, "/sub/pattern/test_pattern.txt"));
+    assert_eq!(pre.len(), post.len());
+}
+
+#[test]
+fn walk_file_types() {
+    let fs = scratch();
+    let mut files = paths(fs.walk("/").file_types(&[FileType::File]));
+    files.sort();
+    assert_eq!(
+        files,
+        vec![
+            "/goodbye.txt",
+            "/hello.txt",
+            "/sub/michelle.jpg",
+            "/sub/pattern/test_pattern.txt",
+        ]
+    );
+    let dirs = paths(fs.walk("/sub").file_types(&[FileType::Directory]));
+    assert_eq!(dirs, vec!["/sub", "/sub/pattern"]);
+}
+
+#[test]
+fn walk_errors() {
+    let fs = scratch();
+    let mut walk = fs.walk("/missing");
+    assert!(walk.next().unwrap().is_err());
+    assert!(walk.next().is_none());
+    assert_eq!(paths(fs.walk("/hello.txt")), vec!["/hello.txt"]);
+}
+
+#[test]
+fn walk_generated_tree() {
+    let fs = formatted(&Mkfs::new(), 8 << 20);
+    let mut expected = vec!["/".to_owned(), "/lost+found".to_owned()];
+    for a in 0..5 {
+        let dir = format!("/d{}", a);
+        fs.create_dir(&dir).unwrap();
+        expected.push(dir.clone());
+        for b in 0..5 {
+            let file = format!("{}/f{}", dir, b);
+            fs.create(&file).unwrap().write_all(b"x").unwrap();
+            expected.push(file);
+        }
+    }
+    let mut walked = paths(fs.walk("/"));
+    walked.sort();
+    expected.sort();
+    assert_eq!(walked, expected);
+}
@@ -0,0 +1,162 @@
+#![cfg(test)]
+
+extern crate ext2;
+
+use std::io::{self, Read, Seek, Write};
+
+use ext2::OpenOptions;
+
+mod common;
+use common::{debugfs, fsck, scratch};
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn create_and_read_back() {
+    let fs = scratch();
+    let data = pattern(10000);
+    fs.create("/sub/new.bin").unwrap().write_all(&data).unwrap();
+    let mut f = fs.open("/sub/new.bin").unwrap();
+    assert_eq!(f.size(), 10000);
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, data);
+    fsck(fs, "create");
+}
+
+#[test]
+fn truncate_existing_file() {
+    let fs = scratch();
+    fs.create("/hello.txt")
+        .unwrap()
+        .write_all(b"Goodbye")
+        .unwrap();
+    let mut buf = String::new();
+    fs.open("/hello.txt")
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "Goodbye");
+    fsck(fs, "truncate");
+}
+
+#[test]
+fn overwrite_and_append() {
+    let fs = scratch();
+    {
+        let mut f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&fs, "/goodbye.txt")
+            .unwrap();
+        f.seek(io::SeekFrom::Start(6)).unwrap();
+        f.write_all(b"World").unwrap();
+    }
+    OpenOptions::new()
+        .append(true)
+        .open(&fs, "/goodbye.txt")
+        .unwrap()
+        .write_all(b"Again\n")
+        .unwrap();
+    let mut buf = String::new();
+    fs.open("/goodbye.txt")
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "Adios Worldos!\nAgain\n");
+    fsck(fs, "append");
+}
+
+#[test]
+fn write_beyond_end_of_block() {
+    let fs = scratch();
+    let mut f = fs.create("/gap.txt").unwrap();
+    f.seek(io::SeekFrom::Start(4090)).unwrap();
+    f.write_all(b"straddling").unwrap();
+    assert_eq!(f.size(), 4100);
+    drop(f);
+    let mut buf = Vec::new();
+    fs.open("/gap.txt").unwrap().read_to_end(&mut buf).unwrap();
+    assert_eq!(&buf[..4090], &[0; 4090][..]);
+    assert_eq!(&buf[4090..], b"straddling");
+    fsck(fs, "straddle");
+}
+
+#[test]
+fn open_options_errors() {
+    let fs = scratch();
+    let err = fs.create("/nowhere/new.txt").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let err = fs.create("/hello.txt/new.txt").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotADirectory);
+    let err = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&fs, "/hello.txt")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    let err = fs.create("/sub").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::IsADirectory);
+    let err = fs.open("/hello.txt").unwrap().write(b"x").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn out_of_space() {
+    let fs = scratch();
+    let mut f = fs.create("/big.bin").unwrap();
+    let err = f.write_all(&pattern(64 * 4096)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    drop(f);
+    fsck(fs, "full");
+}
+
+#[test]
+fn out_of_space_inside_an_indirect_block() {
+    let fs = scratch();
+    // Leave one of the 12 free blocks.
+    let mut f = fs.create("/big.bin").unwrap();
+    f.write_all(&pattern(11 * 4096)).unwrap();
+    // The last free block goes to the indirect block, leaving none for
+    // the data block it would point to.
+    f.seek(io::SeekFrom::Start(20 * 4096)).unwrap();
+    let err = f.write(b"x").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    drop(f);
+    fs.create("/last.bin").unwrap().write_all(b"x").unwrap();
+    fsck(fs, "full-indirect");
+}
+
+#[test]
+fn only_regular_files_are_written() {
+    let fs = match debugfs(&["mknod null c 1 3", "mknod fifo p"]) {
+        Some(fs) => fs,
+        None => return,
+    };
+    let err = OpenOptions::new()
+        .append(true)
+        .truncate(true)
+        .open(&fs, "/sub")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::IsADirectory);
+    for path in &["/null", "/fifo"] {
+        let err = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&fs, path)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        let err = OpenOptions::new()
+            .append(true)
+            .open(&fs, path)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+    fsck(fs, "special-files");
+}